@@ -0,0 +1,429 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::queue::ItemId;
+use crate::utils::{format_bytes, format_timestamp};
+use crate::workshop::{Collection, ItemDetails, RequiredItem};
+
+/// Overrides `api_key` from the config
+pub const KEY_ENV_VAR: &str = "RSW_API_KEY";
+/// Overrides `api_url` from the config, e.g. to test against a local server
+pub const URL_ENV_VAR: &str = "RSW_API_URL";
+
+pub const DEFAULT_URL: &str = "https://api.steampowered.com";
+
+/// Collections made on the website belong to this app rather than the game
+const COLLECTION_APP_ID: u64 = 766;
+
+/// `filetype` of a child that is a collection itself
+const CHILD_COLLECTION: u32 = 2;
+
+/// Items per `QueryFiles` page, the same as the browse page
+const QUERY_PAGE_SIZE: u32 = 30;
+
+/// Client for the Steam Web API endpoints that describe workshop items
+pub struct Client {
+    base_url: String,
+    key: Option<String>,
+    http: reqwest::blocking::Client,
+}
+
+/// A published file as the API describes it. Ids and sizes come as strings
+/// from some endpoints and as numbers from others, `QueryFiles` also names
+/// some fields differently.
+#[derive(Debug, Deserialize)]
+pub struct FileDetails {
+    #[serde(deserialize_with = "number")]
+    pub publishedfileid: u64,
+    #[serde(default)]
+    pub result: Option<u32>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, alias = "file_description")]
+    pub description: String,
+    #[serde(default)]
+    pub creator: Option<String>,
+    #[serde(default, alias = "creator_appid", deserialize_with = "optional_number")]
+    pub creator_app_id: Option<u64>,
+    #[serde(default, alias = "consumer_appid", deserialize_with = "optional_number")]
+    pub consumer_app_id: Option<u64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub file_size: Option<u64>,
+    #[serde(default)]
+    pub time_created: Option<u64>,
+    #[serde(default)]
+    pub time_updated: Option<u64>,
+    #[serde(default)]
+    pub subscriptions: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub vote_data: Option<VoteData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tag {
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoteData {
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub votes_up: u64,
+    #[serde(default)]
+    pub votes_down: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionDetails {
+    #[serde(deserialize_with = "number")]
+    pub publishedfileid: u64,
+    #[serde(default)]
+    pub result: Option<u32>,
+    #[serde(default)]
+    pub children: Vec<Child>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Child {
+    #[serde(deserialize_with = "number")]
+    pub publishedfileid: u64,
+    #[serde(default)]
+    pub filetype: u32,
+}
+
+/// What `QueryFiles` searches for, the same filters as the browse page
+pub struct Query<'a> {
+    pub app_id: &'a str,
+    pub text: &'a str,
+    /// Only items from the last n days, all time when negative
+    pub days: i64,
+    pub page: u32,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    response: T,
+}
+
+#[derive(Deserialize)]
+struct FileDetailsResponse {
+    #[serde(default)]
+    publishedfiledetails: Vec<FileDetails>,
+}
+
+#[derive(Deserialize)]
+struct CollectionDetailsResponse {
+    #[serde(default)]
+    collectiondetails: Vec<CollectionDetails>,
+}
+
+impl Client {
+    pub fn new(base_url: String, key: Option<String>) -> Self {
+        Client { base_url: base_url.trim_end_matches('/').to_string(), key, http: reqwest::blocking::Client::new() }
+    }
+
+    /// `ISteamRemoteStorage/GetPublishedFileDetails`, doesn't need a key
+    pub fn published_file_details(&self, ids: &[ItemId]) -> Result<Vec<FileDetails>, String> {
+        let mut form = vec![(String::from("itemcount"), ids.len().to_string())];
+        form.extend(ids.iter().enumerate().map(|(i, id)| (format!("publishedfileids[{}]", i), id.to_string())));
+
+        let response: FileDetailsResponse = self.post("ISteamRemoteStorage/GetPublishedFileDetails/v1/", form)?;
+        Ok(response.publishedfiledetails)
+    }
+
+    /// `ISteamRemoteStorage/GetCollectionDetails`, doesn't need a key. The
+    /// children of an item that isn't a collection are its required items.
+    pub fn collection_details(&self, ids: &[ItemId]) -> Result<Vec<CollectionDetails>, String> {
+        let mut form = vec![(String::from("collectioncount"), ids.len().to_string())];
+        form.extend(ids.iter().enumerate().map(|(i, id)| (format!("publishedfileids[{}]", i), id.to_string())));
+
+        let response: CollectionDetailsResponse = self.post("ISteamRemoteStorage/GetCollectionDetails/v1/", form)?;
+        Ok(response.collectiondetails)
+    }
+
+    /// `IPublishedFileService/QueryFiles`, ranked by votes like the browse page. Needs a key.
+    pub fn query_files(&self, query: &Query) -> Result<Vec<FileDetails>, String> {
+        let key = self.key.as_ref().ok_or_else(|| format!(
+            "Searching through the API needs a Steam Web API key, set 'api_key' in config.json or {}",
+            KEY_ENV_VAR
+        ))?;
+
+        let mut params = vec![
+            ("key", key.clone()),
+            ("appid", query.app_id.to_string()),
+            ("search_text", query.text.to_string()),
+            ("page", query.page.to_string()),
+            ("numperpage", QUERY_PAGE_SIZE.to_string()),
+            ("return_metadata", String::from("true")),
+            ("return_tags", String::from("true")),
+            ("return_vote_data", String::from("true")),
+        ];
+        match query.days {
+            // 0 is RankedByVote, 3 is RankedByTrend which is the only one taking `days`
+            days if days > 0 => params.extend([("query_type", String::from("3")), ("days", days.to_string())]),
+            _ => params.push(("query_type", String::from("0")))
+        }
+
+        let url = format!("{}/IPublishedFileService/QueryFiles/v1/", self.base_url);
+        let text = self.http.get(url).query(&params).send()
+            .and_then(|res| res.error_for_status())
+            .and_then(|res| res.text())
+            .map_err(|err| Client::error(&err))?;
+
+        decode::<FileDetailsResponse>(&text).map(|x| x.publishedfiledetails)
+    }
+
+    /// The same details `workshop::fetch_item` reads from the page. `Ok(None)`
+    /// when steam has no item with that id. The API has no ratings on
+    /// this endpoint and no required DLC at all.
+    pub fn item_details(&self, id: ItemId) -> Result<Option<ItemDetails>, String> {
+        let details = match self.published_file_details(&[id])?.into_iter().next() {
+            Some(details) if details.result == Some(1) => details,
+            _ => return Ok(None)
+        };
+
+        let required: Vec<ItemId> = Client::children(id, self.collection_details(&[id])?).iter()
+            .map(|x| ItemId(x.publishedfileid))
+            .collect();
+        let required_items = match required.is_empty() {
+            true => vec![],
+            false => self.published_file_details(&required)?.into_iter()
+                .map(|x| RequiredItem { id: ItemId(x.publishedfileid), title: x.title })
+                .collect()
+        };
+
+        let mut item = Client::to_item_details(details);
+        item.required_items = required_items;
+
+        Ok(Some(item))
+    }
+
+    /// The same as `workshop::fetch_collection`. `Ok(None)` when the id
+    /// isn't a collection.
+    pub fn collection(&self, id: ItemId) -> Result<Option<Collection>, String> {
+        let details = match self.published_file_details(&[id])?.into_iter().next() {
+            Some(details) if Client::is_collection(&details) => details,
+            _ => return Ok(None)
+        };
+
+        let children = Client::children(id, self.collection_details(&[id])?);
+        let ids: Vec<ItemId> = children.iter().map(|x| ItemId(x.publishedfileid)).collect();
+        let titled = match ids.is_empty() {
+            true => vec![],
+            false => self.published_file_details(&ids)?
+        };
+
+        Ok(Some(Client::to_collection(details, &children, &titled)))
+    }
+
+    /// Collections are made on the website, so they belong to its app instead of the game
+    fn is_collection(details: &FileDetails) -> bool {
+        details.result == Some(1) && details.creator_app_id == Some(COLLECTION_APP_ID)
+    }
+
+    /// The children `GetCollectionDetails` lists for `id`, none when it doesn't know the id
+    fn children(id: ItemId, details: Vec<CollectionDetails>) -> Vec<Child> {
+        details.into_iter()
+            .filter(|x| x.publishedfileid == id.0 && x.result == Some(1))
+            .flat_map(|x| x.children)
+            .collect()
+    }
+
+    /// Splits the children into items and linked collections, titled from `titled`
+    fn to_collection(details: FileDetails, children: &[Child], titled: &[FileDetails]) -> Collection {
+        let title = |id: u64| titled.iter()
+            .find(|x| x.publishedfileid == id)
+            .map(|x| x.title.clone())
+            .unwrap_or_default();
+
+        let (collections, items): (Vec<&Child>, Vec<&Child>) = children.iter().partition(|x| x.filetype == CHILD_COLLECTION);
+
+        Collection {
+            id: ItemId(details.publishedfileid),
+            app_id: details.consumer_app_id.map(|x| x.to_string()),
+            title: details.title,
+            items: items.iter().map(|x| (ItemId(x.publishedfileid), title(x.publishedfileid))).collect(),
+            collections: collections.iter().map(|x| (ItemId(x.publishedfileid), title(x.publishedfileid))).collect(),
+        }
+    }
+
+    pub fn to_item_details(details: FileDetails) -> ItemDetails {
+        let rating = details.vote_data.map(|x| format!(
+            "{:.0}% ({} up, {} down)",
+            x.score * 100.0, x.votes_up, x.votes_down
+        ));
+
+        ItemDetails {
+            id: ItemId(details.publishedfileid),
+            app_id: details.consumer_app_id.map(|x| x.to_string()),
+            title: details.title,
+            // a steam id, the API doesn't have the name
+            author: details.creator,
+            size: details.file_size.map(format_bytes),
+            posted: details.time_created.map(format_timestamp),
+            updated: details.time_updated.map(format_timestamp),
+            tags: details.tags.into_iter().map(|x| x.tag).collect(),
+            rating,
+            subscribers: details.subscriptions,
+            description: details.description,
+            required_items: vec![],
+            required_dlc: vec![],
+            fetched_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs()),
+        }
+    }
+
+    fn post<T: for<'de> Deserialize<'de>>(&self, endpoint: &str, mut form: Vec<(String, String)>) -> Result<T, String> {
+        if let Some(key) = &self.key {
+            form.push((String::from("key"), key.clone()));
+        }
+
+        let text = self.http.post(format!("{}/{}", self.base_url, endpoint)).form(&form).send()
+            .and_then(|res| res.error_for_status())
+            .and_then(|res| res.text())
+            .map_err(|err| Client::error(&err))?;
+
+        decode(&text)
+    }
+
+    fn error(err: &reqwest::Error) -> String {
+        match err.status() {
+            Some(status) if status.as_u16() == 401 || status.as_u16() == 403 => format!(
+                "The Steam Web API refused the request ({}), check 'api_key' in config.json or {}",
+                status, KEY_ENV_VAR
+            ),
+            Some(status) => format!("The Steam Web API answered with {}", status),
+            None => String::from("Couldn't access the Steam Web API at this time")
+        }
+    }
+}
+
+/// Every endpoint wraps what it answers in `{"response": ...}`
+fn decode<T: for<'de> Deserialize<'de>>(text: &str) -> Result<T, String> {
+    serde_json::from_str::<Response<T>>(text)
+        .map(|x| x.response)
+        .map_err(|err| format!("Unexpected response from the Steam Web API: {}", err))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    String(String),
+    Number(u64),
+}
+
+impl StringOrNumber {
+    fn value<E: serde::de::Error>(self) -> Result<u64, E> {
+        match self {
+            StringOrNumber::Number(x) => Ok(x),
+            StringOrNumber::String(x) => x.parse::<u64>().map_err(E::custom)
+        }
+    }
+}
+
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    StringOrNumber::deserialize(deserializer)?.value()
+}
+
+fn optional_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<StringOrNumber>::deserialize(deserializer)? {
+        Some(x) => x.value().map(Some),
+        None => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_DETAILS: &str = include_str!("../fixtures/api/published_file_details.json");
+    const COLLECTION_DETAILS: &str = include_str!("../fixtures/api/collection_details.json");
+    const QUERY_FILES: &str = include_str!("../fixtures/api/query_files.json");
+
+    fn file_details() -> Vec<FileDetails> {
+        decode::<FileDetailsResponse>(FILE_DETAILS).unwrap().publishedfiledetails
+    }
+
+    fn file(id: u64) -> FileDetails {
+        file_details().into_iter().find(|x| x.publishedfileid == id).unwrap()
+    }
+
+    #[test]
+    fn strings_and_numbers() {
+        let files = file_details();
+        let ids: Vec<u64> = files.iter().map(|x| x.publishedfileid).collect();
+        assert_eq!(ids, [111, 5, 222, 900, 404]);
+
+        // GetPublishedFileDetails sends sizes as numbers, QueryFiles as strings
+        assert_eq!(file(111).file_size, Some(12945408));
+        assert_eq!(file(5).file_size, Some(0));
+        assert_eq!(file(900).file_size, None);
+
+        let missing = file(404);
+        assert_eq!(missing.result, Some(9));
+        assert_eq!(missing.creator_app_id, None);
+        assert!(missing.title.is_empty());
+
+        let queried = decode::<FileDetailsResponse>(QUERY_FILES).unwrap().publishedfiledetails;
+        assert_eq!(queried[0].publishedfileid, 111);
+        assert_eq!(queried[0].file_size, Some(12945408));
+        assert_eq!(queried[0].consumer_app_id, Some(294100));
+        assert_eq!(queried[0].description, "Guests visit your colony. They pay for beds and food.");
+
+        assert!(serde_json::from_str::<Child>(r#"{"publishedfileid": "abc"}"#).is_err());
+        assert!(serde_json::from_str::<FileDetails>(r#"{"publishedfileid": 1, "file_size": null}"#).unwrap().file_size.is_none());
+        assert!(decode::<FileDetailsResponse>("<html>Too many requests</html>").is_err());
+    }
+
+    #[test]
+    fn collections() {
+        let details = decode::<CollectionDetailsResponse>(COLLECTION_DETAILS).unwrap().collectiondetails;
+
+        assert!(Client::is_collection(&file(5)));
+        assert!(Client::is_collection(&file(900)));
+        assert!(!Client::is_collection(&file(111)));
+        assert!(!Client::is_collection(&file(404)));
+
+        let children = Client::children(ItemId(5), details);
+        let collection = Client::to_collection(file(5), &children, &file_details());
+
+        assert_eq!(collection.id, ItemId(5));
+        assert_eq!(collection.app_id.as_deref(), Some("294100"));
+        assert_eq!(collection.title, "Our Modpack");
+        assert_eq!(collection.items, [(ItemId(111), String::from("Hospitality")), (ItemId(222), String::from("Harmony"))]);
+        assert_eq!(collection.collections, [(ItemId(900), String::from("QoL Pack"))]);
+
+        // the children of an item are its required items, an unknown id has none
+        let details = decode::<CollectionDetailsResponse>(COLLECTION_DETAILS).unwrap().collectiondetails;
+        let required: Vec<u64> = Client::children(ItemId(111), details).iter().map(|x| x.publishedfileid).collect();
+        assert_eq!(required, [222]);
+
+        let details = decode::<CollectionDetailsResponse>(COLLECTION_DETAILS).unwrap().collectiondetails;
+        assert!(Client::children(ItemId(404), details).is_empty());
+    }
+
+    #[test]
+    fn item_details() {
+        let item = Client::to_item_details(file(111));
+
+        assert_eq!(item.id, ItemId(111));
+        assert_eq!(item.app_id.as_deref(), Some("294100"));
+        assert_eq!(item.title, "Hospitality");
+        assert_eq!(item.author.as_deref(), Some("76561198000000000"));
+        assert_eq!(item.size, Some(format_bytes(12945408)));
+        assert_eq!(item.posted, Some(format_timestamp(1488461940)));
+        assert_eq!(item.updated, Some(format_timestamp(1792000000)));
+        assert_eq!(item.tags, ["Mod", "1.4"]);
+        assert_eq!(item.rating, None);
+        assert_eq!(item.subscribers, Some(234567));
+        assert!(item.required_items.is_empty() && item.required_dlc.is_empty());
+
+        let queried = decode::<FileDetailsResponse>(QUERY_FILES).unwrap().publishedfiledetails;
+        let item = Client::to_item_details(queried.into_iter().next().unwrap());
+        assert_eq!(item.rating.as_deref(), Some("93% (900 up, 60 down)"));
+        assert_eq!(item.description, "Guests visit your colony. They pay for beds and food.");
+    }
+}
@@ -1,86 +1,313 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use crate::utils::{log, LogLevel};
+use crate::commands::{OptionSpec, ValueKind};
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    UnterminatedQuote(char),
+    TrailingEscape,
+    MissingValue(String),
+    UnexpectedValue(String),
+    UnknownOption(String),
+    UnknownShortOption(char),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "No command given"),
+            ParseError::UnterminatedQuote(quote) => write!(f, "Missing closing {}", quote),
+            ParseError::TrailingEscape => write!(f, "Nothing to escape after '\\'"),
+            ParseError::MissingValue(option) => write!(f, "No value found for '{}'", option),
+            ParseError::UnexpectedValue(option) => write!(f, "'{}' doesn't take a value", option),
+            ParseError::UnknownOption(option) => write!(f, "'{}' is not a valid option", option),
+            ParseError::UnknownShortOption(ch) => write!(f, "'-{}' is not a valid option", ch),
+        }
+    }
+}
 
 pub struct InputParser{
     pub command: String,
     pub args: Vec<String>,
+    /// Keyed by the long name including dashes (`--pages`), flags map to an empty string
     pub options: HashMap<String, String>
 }
 
 
 impl InputParser {
-    pub fn new(input: String) -> Self {
-        let mut list = InputParser::parse(input);
+    /// Builds a parser from already split tokens, e.g. the process arguments.
+    ///
+    /// The first token is the command, options and positional args can be
+    /// mixed freely after that. `specs` are the options the command accepts,
+    /// a lone `--` ends option parsing.
+    pub fn from_tokens(tokens: Vec<String>, specs: &[OptionSpec]) -> Result<Self, ParseError> {
+        let mut tokens = tokens.into_iter();
+        let command = match tokens.next() {
+            Some(command) => command,
+            None => return Err(ParseError::Empty)
+        };
 
-        // assert!(list.len() > 0);
-        // assert!(!list[0].starts_with("--"), "First argument must be a command");
+        let mut positional: Vec<String> = vec![];
+        let mut options: HashMap<String, String> = HashMap::new();
+        let mut only_positional = false;
 
-        let mut option_offset: usize = 0;
-        for (i, mut item) in list.clone().into_iter().enumerate() {
-            item = item.trim().to_string();
-            list[i] = item.clone();
+        while let Some(token) = tokens.next() {
+            if only_positional || !InputParser::is_option(&token) {
+                positional.push(token);
+            }
 
-            if item.starts_with("--") {
-                break
+            else if token == "--" {
+                only_positional = true;
             }
 
-            option_offset += 1
-        }
+            else if let Some(long) = token.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                    None => (token.clone(), None)
+                };
+
+                let spec = match specs.iter().find(|spec| spec.name == name) {
+                    Some(spec) => spec,
+                    None => return Err(ParseError::UnknownOption(name))
+                };
 
-        let mut options_hashmap: HashMap<String, String> = HashMap::new();
-        for item in list[option_offset..].chunks(2) {
-            if item.len() <= 1 {
-                log(
-                    LogLevel::ERR, 
-                    format!("No value found for '{}'", item[0])
-                );
-                continue;
+                InputParser::insert_option(&mut options, spec, value, &mut tokens)?;
             }
 
-            options_hashmap.insert(item[0].to_string(), item[1].to_string());
-        };
+            else {
+                // short options, either `-p 2`, `-p2` or a cluster of flags like `-kq`
+                let shorts: Vec<char> = token.chars().skip(1).collect();
 
-        InputParser { 
-            command: list[0].to_owned(), 
-            args: list[1..option_offset].to_owned(), 
-            options: options_hashmap
+                for (i, ch) in shorts.iter().enumerate() {
+                    let spec = match specs.iter().find(|spec| spec.short == Some(*ch)) {
+                        Some(spec) => spec,
+                        None => return Err(ParseError::UnknownShortOption(*ch))
+                    };
+
+                    if spec.kind == ValueKind::Flag {
+                        options.insert(spec.name.to_string(), String::new());
+                        continue;
+                    }
+
+                    let rest: String = shorts[i + 1..].iter().collect();
+                    let value = if rest.is_empty() { None } else { Some(rest) };
+
+                    InputParser::insert_option(&mut options, spec, value, &mut tokens)?;
+                    break;
+                }
+            }
         }
+
+        Ok(InputParser {
+            command,
+            args: positional,
+            options
+        })
     }
 
-    fn parse(text: String) -> Vec<String> {
+    /// Splits a line into tokens on whitespace.
+    ///
+    /// Double quotes group words and allow `\"` and `\\` inside them, single
+    /// quotes group words literally. Outside of quotes a backslash escapes
+    /// whitespace, quotes and itself, anything else is kept as is so windows
+    /// paths don't need doubling.
+    pub fn tokenize(text: &str) -> Result<Vec<String>, ParseError> {
         let mut list: Vec<String> = vec![];
-        let mut flag: usize = 0;
-
         let mut temp = String::new();
-        for ch in text.chars() {
-            if ch == ' ' && flag == 0 {
-                list.push(temp.clone());
-                temp.clear();
-            }
+        // a token can be empty when it's quoted, `""` is a valid (empty) arg
+        let mut in_token = false;
 
-            else if ch == ' ' && flag > 0 {
-                temp.push(' ');
-            }
-
-            else if ch == '"' {
-                flag += 1;
-                if flag == 2 {
-                    list.push(temp.clone());
-                    temp.clear();
-                    flag = 0;
+        let mut chars = text.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                c if c.is_whitespace() => {
+                    if in_token {
+                        list.push(std::mem::take(&mut temp));
+                        in_token = false;
+                    }
+                },
+                '\'' => {
+                    in_token = true;
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => temp.push(c),
+                            None => return Err(ParseError::UnterminatedQuote('\''))
+                        }
+                    }
+                },
+                '"' => {
+                    in_token = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.peek() {
+                                Some(&c) if c == '"' || c == '\\' => {
+                                    temp.push(c);
+                                    chars.next();
+                                },
+                                _ => temp.push('\\')
+                            },
+                            Some(c) => temp.push(c),
+                            None => return Err(ParseError::UnterminatedQuote('"'))
+                        }
+                    }
+                },
+                '\\' => {
+                    in_token = true;
+                    match chars.peek() {
+                        Some(&c) if c.is_whitespace() || c == '"' || c == '\'' || c == '\\' => {
+                            temp.push(c);
+                            chars.next();
+                        },
+                        Some(_) => temp.push('\\'),
+                        None => return Err(ParseError::TrailingEscape)
+                    }
+                },
+                c => {
+                    in_token = true;
+                    temp.push(c);
                 }
             }
+        }
 
-            else {
-                temp.push(ch);
-            }
+        if in_token {
+            list.push(temp);
         }
-        
-        if !temp.is_empty() {
-            list.push(temp.trim().to_string().clone());
+
+        Ok(list)
+    }
+
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    /// `-` followed by something that isn't a number, so `-1` stays a value
+    fn is_option(token: &str) -> bool {
+        token.starts_with('-')
+            && token.len() > 1
+            && !token[1..].starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn insert_option(
+        options: &mut HashMap<String, String>,
+        spec: &OptionSpec,
+        value: Option<String>,
+        tokens: &mut impl Iterator<Item = String>
+    ) -> Result<(), ParseError> {
+        if spec.kind == ValueKind::Flag {
+            if value.is_some() {
+                return Err(ParseError::UnexpectedValue(spec.name.to_string()));
+            }
+
+            options.insert(spec.name.to_string(), String::new());
+            return Ok(());
         }
 
-        list
+        let value = match value {
+            Some(value) => value,
+            None => match tokens.next() {
+                Some(value) => value,
+                None => return Err(ParseError::MissingValue(spec.name.to_string()))
+            }
+        };
+
+        options.insert(spec.name.to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPECS: &[OptionSpec] = &[
+        OptionSpec { name: "--pages", short: Some('p'), kind: ValueKind::Number, default: Some("1"), description: "" },
+        OptionSpec { name: "--days", short: Some('d'), kind: ValueKind::Number, default: Some("-1"), description: "" },
+        OptionSpec { name: "--keep-going", short: Some('k'), kind: ValueKind::Flag, default: None, description: "" },
+        OptionSpec { name: "--quiet", short: Some('q'), kind: ValueKind::Flag, default: None, description: "" },
+    ];
+
+    fn tokens(text: &str) -> Vec<String> {
+        InputParser::tokenize(text).unwrap()
+    }
+
+    fn parse(text: &str) -> Result<InputParser, ParseError> {
+        InputParser::from_tokens(tokens(text), SPECS)
+    }
+
+    #[test]
+    fn tokenize_quotes_and_escapes() {
+        assert_eq!(tokens("search rimworld hospital"), ["search", "rimworld", "hospital"]);
+        assert_eq!(tokens("  search   \"rim world\"  "), ["search", "rim world"]);
+        assert_eq!(tokens(r#"say "a \"quoted\" word" "back\\slash""#), ["say", "a \"quoted\" word", "back\\slash"]);
+        assert_eq!(tokens(r#"say 'single "quotes" \n are literal'"#), ["say", "single \"quotes\" \\n are literal"]);
+        assert_eq!(tokens(r#"convert C:\mods\list.txt my\ file \"x\""#), ["convert", "C:\\mods\\list.txt", "my file", "\"x\""]);
+        assert_eq!(tokens(r#"say "" ''"#), ["say", "", ""]);
+        assert_eq!(tokens("a\"b c\"d"), ["ab cd"]);
+    }
+
+    #[test]
+    fn tokenize_errors() {
+        assert_eq!(InputParser::tokenize("say \"open"), Err(ParseError::UnterminatedQuote('"')));
+        assert_eq!(InputParser::tokenize("say 'open"), Err(ParseError::UnterminatedQuote('\'')));
+        assert_eq!(InputParser::tokenize("say trailing\\"), Err(ParseError::TrailingEscape));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn empty_line() {
+        assert!(tokens("").is_empty());
+        assert!(tokens("   \t ").is_empty());
+        assert_eq!(parse("").err(), Some(ParseError::Empty));
+    }
+
+    #[test]
+    fn long_options() {
+        let parsed = parse("search rimworld --pages 2 hospital --days=7 --keep-going").unwrap();
+
+        assert_eq!(parsed.command, "search");
+        assert_eq!(parsed.args, ["rimworld", "hospital"]);
+        assert_eq!(parsed.options["--pages"], "2");
+        assert_eq!(parsed.options["--days"], "7");
+        assert!(parsed.has_flag("--keep-going"));
+        assert!(!parsed.has_flag("--quiet"));
+    }
+
+    #[test]
+    fn short_options() {
+        let parsed = parse("search -p 2 -d7 -kq").unwrap();
+        assert_eq!(parsed.options["--pages"], "2");
+        assert_eq!(parsed.options["--days"], "7");
+        assert!(parsed.has_flag("--keep-going") && parsed.has_flag("--quiet"));
+
+        // a value ends the cluster
+        let parsed = parse("search -kp3").unwrap();
+        assert!(parsed.has_flag("--keep-going"));
+        assert_eq!(parsed.options["--pages"], "3");
+    }
+
+    #[test]
+    fn negative_numbers_and_double_dash() {
+        let parsed = parse("search --days -1 -5").unwrap();
+        assert_eq!(parsed.options["--days"], "-1");
+        assert_eq!(parsed.args, ["-5"]);
+
+        let parsed = parse("search -d -1").unwrap();
+        assert_eq!(parsed.options["--days"], "-1");
+
+        let parsed = parse("search -k -- --pages -q").unwrap();
+        assert!(parsed.has_flag("--keep-going"));
+        assert_eq!(parsed.args, ["--pages", "-q"]);
+        assert!(!parsed.options.contains_key("--pages"));
+    }
+
+    #[test]
+    fn option_errors() {
+        assert_eq!(parse("search --nope").err(), Some(ParseError::UnknownOption(String::from("--nope"))));
+        assert_eq!(parse("search --nope=1").err(), Some(ParseError::UnknownOption(String::from("--nope"))));
+        assert_eq!(parse("search -x").err(), Some(ParseError::UnknownShortOption('x')));
+        assert_eq!(parse("search --pages").err(), Some(ParseError::MissingValue(String::from("--pages"))));
+        assert_eq!(parse("search --quiet=yes").err(), Some(ParseError::UnexpectedValue(String::from("--quiet"))));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{cli::InputParser, utils::{log, underscorize, LogLevel, ErrorKind}, manager::{Config, Manager}, commands::{Command, CommandSpec, ArgSpec, ValueKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "accounts",
+    aliases: &["account"],
+    args: &[
+        ArgSpec {
+            name: "action",
+            kind: ValueKind::Text,
+            required: true,
+            required_for: &[],
+            choices: &["show", "set", "remove", "use"],
+            description: "show, set [name] [username], remove [name] or use [alias] [name]",
+        },
+        ArgSpec { name: "name", kind: ValueKind::Text, required: false, required_for: &["set", "remove", "use"], choices: &[], description: "Name of the account, or the alias for 'use'" },
+        ArgSpec { name: "value", kind: ValueKind::Text, required: false, required_for: &["set"], choices: &[], description: "Steam username, or the account name for 'use' (none to go back to anonymous)" },
+    ],
+    options: &[],
+    description: "Shows and edits the steam accounts used for downloads",
+    examples: &["accounts show", "accounts set main my_steam_name", "accounts use arma3 main", "accounts use arma3", "accounts remove main"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    AccountCommand::new(&mut manager.config, data).run()
+}
+
+pub struct AccountCommand<'a> {
+    pub data: InputParser,
+    pub config: &'a mut Config
+}
+
+impl<'a> Command<'a, ()> for AccountCommand<'a> {
+    fn new(config: &'a mut Config, data: InputParser) -> Self {
+        AccountCommand { data, config }
+    }
+
+    fn run(&mut self) -> Result<(), ErrorKind> {
+        match self.data.args[0].as_str() {
+            "show" => self.display_accounts(),
+            "set" => self.set(),
+            "remove" => self.remove(),
+            "use" => self.use_for_alias(),
+            _ => {
+                log(
+                    LogLevel::ERR,
+                    format!("'{}' is not a valid command", self.data.args[0])
+                );
+                Err(ErrorKind::Usage)
+            }
+        }
+    }
+}
+
+impl<'a> AccountCommand<'a> {
+    pub fn display_accounts(&self) -> Result<(), ErrorKind> {
+        match self.config.get_props_ref() {
+            Some(props) if !props.accounts.is_empty() => {
+                println!("{}", "-".repeat(60));
+
+                for (name, username) in props.accounts.iter() {
+                    let mut aliases: Vec<&str> = props.alias_accounts.iter()
+                        .filter(|(_, account)| *account == name)
+                        .map(|(alias, _)| alias.as_str())
+                        .collect();
+                    aliases.sort();
+
+                    println!(
+                        "{0: <30} | {1: <30}",
+                        name, username
+                    );
+                    if !aliases.is_empty() {
+                        println!("  used by {}", aliases.join(", "));
+                    }
+                    println!("{}", "-".repeat(60));
+                }
+            },
+
+            _ => log(
+                LogLevel::INFO,
+                String::from("No accounts found, everything is downloaded anonymously")
+            )
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self) -> Result<(), ErrorKind> {
+        match self.config.get_props_mut() {
+            Some(props) => props.set_account(self.data.args[1].clone(), self.data.args[2].clone()),
+            None => log(
+                LogLevel::WARN,
+                String::from("No config found")
+            )
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self) -> Result<(), ErrorKind> {
+        match self.config.get_props_mut() {
+            Some(props) => if !props.remove_account(self.data.args[1].clone()) {
+                return Err(ErrorKind::Usage);
+            },
+            None => log(
+                LogLevel::WARN,
+                String::from("No config found")
+            )
+        }
+
+        Ok(())
+    }
+
+    /// Sets the account an alias downloads with, or goes back to anonymous without one
+    pub fn use_for_alias(&mut self) -> Result<(), ErrorKind> {
+        let props = match self.config.get_props_mut() {
+            Some(props) => props,
+            None => {
+                log(
+                    LogLevel::WARN,
+                    String::from("No config found")
+                );
+                return Ok(());
+            }
+        };
+
+        let alias = underscorize(self.data.args[1].clone());
+        if !props.aliases.contains_key(&alias) {
+            log(
+                LogLevel::ERR,
+                format!("No alias found for '{}'", alias)
+            );
+            return Err(ErrorKind::MissingAlias);
+        }
+
+        match self.data.args.get(2) {
+            Some(name) if props.accounts.contains_key(name) => {
+                props.alias_accounts.insert(alias.clone(), name.clone());
+                log(
+                    LogLevel::INFO,
+                    format!("'{}' downloads with account '{}'", alias, name)
+                );
+            },
+            Some(name) => {
+                log(
+                    LogLevel::ERR,
+                    format!("No account named '{}'", name)
+                );
+                return Err(ErrorKind::Usage);
+            },
+            None => {
+                props.alias_accounts.remove(&alias);
+                log(
+                    LogLevel::INFO,
+                    format!("'{}' downloads anonymously", alias)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,36 @@
-use crate::{cli::InputParser, utils::{log, LogLevel}, manager::Config, commands::Command};
+use crate::{cli::InputParser, utils::{log, underscorize, LogLevel, ErrorKind}, manager::{Config, Manager}, commands::{Command, CommandSpec, ArgSpec, ValueKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "aliases",
+    aliases: &["alias"],
+    args: &[
+        ArgSpec {
+            name: "action",
+            kind: ValueKind::Text,
+            required: true,
+            required_for: &[],
+            choices: &["show", "set", "remove", "dir"],
+            description: "show, set [name] [appID], remove [name] or dir [name] [dir]",
+        },
+        ArgSpec { name: "name", kind: ValueKind::Alias, required: false, required_for: &["set", "remove", "dir"], choices: &[], description: "Name of the alias" },
+        ArgSpec {
+            name: "value",
+            kind: ValueKind::Text,
+            required: false,
+            required_for: &["set"],
+            choices: &[],
+            description: "Steam app id the alias points to, or the install dir for 'dir' (none to go back to steamcmd's)",
+        },
+    ],
+    options: &[],
+    description: "Shows and edits app aliases",
+    examples: &["aliases show", "aliases set rimworld 294100", "aliases dir arma3 /srv/arma3", "aliases dir arma3", "aliases remove rimworld"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    AliasCommand::new(&mut manager.config, data).run()
+}
 
 pub struct AliasCommand<'a> {
     pub data: InputParser,
@@ -10,37 +42,25 @@ impl<'a> Command<'a, ()> for AliasCommand<'a> {
         AliasCommand { data, config }
     }
 
-    fn run(&mut self) {
-        let assertion = self.assert();
-        if assertion.is_err() {
-            log(
-                LogLevel::ERR,
-                format!("{}", assertion.unwrap_err())
-            );
-            return;
-        }
+    fn run(&mut self) -> Result<(), ErrorKind> {
         match self.data.args[0].as_str() {
             "show" => self.display_aliases(),
             "set" => self.set(),
             "remove" => self.remove(),
-            _ => log(
-                LogLevel::ERR, 
-                format!("'{}' is not a valid command", self.data.args[0])
-            ),
-        }
-    }
-
-    fn assert(&self) -> Result<(), String> {
-        if self.data.args.len() == 0 {
-            return Err(String::from("Insufficient arguments"))
+            "dir" => self.install_dir(),
+            _ => {
+                log(
+                    LogLevel::ERR, 
+                    format!("'{}' is not a valid command", self.data.args[0])
+                );
+                Err(ErrorKind::Usage)
+            }
         }
-
-        Ok(())
     }
 }
 
 impl<'a> AliasCommand<'a> {
-    pub fn display_aliases(&self) {
+    pub fn display_aliases(&self) -> Result<(), ErrorKind> {
         match self.config.get_props_ref() {
             Some(props) => {
                 println!("{}", "-".repeat(60));
@@ -50,32 +70,29 @@ impl<'a> AliasCommand<'a> {
                         "{0: <30} | {1: <30}",
                         k, v
                     );
+                    if let Some(dir) = props.alias_install_dirs.get(&k) {
+                        println!("  installs into {}", dir);
+                    }
                     println!("{}", "-".repeat(60));
                 }
             },
 
             None => log(
                 LogLevel::WARN, 
-                format!("No aliases found")
+                String::from("No aliases found")
             )
         }
-    }
 
-    pub fn set(&mut self) {
-        if self.data.args.len() < 3 {
-            log(
-                LogLevel::ERR, 
-                format!("Insufficient arguments")
-            );
-            return;
-        }
+        Ok(())
+    }
 
+    pub fn set(&mut self) -> Result<(), ErrorKind> {
         if !self.data.args[2].chars().all(char::is_numeric) {
             log(
                 LogLevel::ERR, 
-                format!("App id must only contains numbers")
+                String::from("App id must only contains numbers")
             );
-            return;
+            return Err(ErrorKind::Usage);
         }
 
         match self.config.get_props_mut() {
@@ -84,26 +101,66 @@ impl<'a> AliasCommand<'a> {
             ),
             None => log(
                 LogLevel::WARN, 
-                format!("No config found")
+                String::from("No config found")
             )
         }
-    }
 
-    pub fn remove(&mut self) {
-        if self.data.args.len() < 2 {
-            log(
-                LogLevel::ERR, 
-                format!("Insufficient arguments")
-            );
-            return;
-        }
+        Ok(())
+    }
 
+    pub fn remove(&mut self) -> Result<(), ErrorKind> {
         match self.config.get_props_mut() {
-            Some(props) => props.remove_alias(self.data.args[1].clone()),
+            Some(props) => if !props.remove_alias(self.data.args[1].clone()) {
+                return Err(ErrorKind::MissingAlias);
+            },
             None => log(
                 LogLevel::WARN, 
-                format!("No config found")
+                String::from("No config found")
             )
         }
+
+        Ok(())
+    }
+
+    /// Sets the dir steamcmd installs an alias into, or goes back to steamcmd's own without one
+    pub fn install_dir(&mut self) -> Result<(), ErrorKind> {
+        let props = match self.config.get_props_mut() {
+            Some(props) => props,
+            None => {
+                log(
+                    LogLevel::WARN,
+                    String::from("No config found")
+                );
+                return Ok(());
+            }
+        };
+
+        let alias = underscorize(self.data.args[1].clone());
+        if !props.aliases.contains_key(&alias) {
+            log(
+                LogLevel::ERR,
+                format!("No alias found for '{}'", alias)
+            );
+            return Err(ErrorKind::MissingAlias);
+        }
+
+        match self.data.args.get(2) {
+            Some(dir) => {
+                props.alias_install_dirs.insert(alias.clone(), dir.clone());
+                log(
+                    LogLevel::INFO,
+                    format!("'{}' installs into '{}'", alias, dir)
+                );
+            },
+            None => {
+                props.alias_install_dirs.remove(&alias);
+                log(
+                    LogLevel::INFO,
+                    format!("'{}' installs into steamcmd's own dir", alias)
+                );
+            }
+        }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use crate::{cli::InputParser, manager::Manager, queue::{ItemId, Source, TitledItems}, commands::{CommandSpec, ArgSpec, OptionSpec, ValueKind, read_selection}, workshop::{self, Collection, MetadataSource}, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "collection",
+    aliases: &["col"],
+    args: &[
+        ArgSpec { name: "collection", kind: ValueKind::Text, required: true, required_for: &[], choices: &[], description: "Collection id or link to the collection page" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--select",
+            short: Some('s'),
+            kind: ValueKind::Text,
+            default: None,
+            description: "'all' or indices like \"0 1 2\" instead of asking",
+        },
+        OptionSpec {
+            name: "--with-deps",
+            short: Some('w'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Also add the items they require, and the items those require",
+        },
+        OptionSpec {
+            name: "--source",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "page or api, 'metadata_source' in the config by default",
+        },
+    ],
+    description: "Lists the items of a workshop collection and the collections in it, and adds them",
+    examples: &["collection 1884025115", "collection https://steamcommunity.com/sharedfiles/filedetails/?id=1884025115 --select all", "collection 1884025115 --source api"],
+    handler: handle,
+};
+
+/// An item of a collection, with the collection it was found in
+struct Child {
+    id: ItemId,
+    title: String,
+    collection: ItemId,
+    collection_title: String
+}
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let id = match workshop::parse_id(&data.args[0]) {
+        Some(id) => id,
+        None => {
+            log(
+                LogLevel::ERR,
+                format!("'{}' is not a collection id or a link to a collection", data.args[0])
+            );
+            return Err(ErrorKind::Usage);
+        }
+    };
+
+    let source = manager.metadata_source(&data)?;
+    let (app_id, children) = resolve(manager, id, source)?;
+    if children.is_empty() {
+        log(
+            LogLevel::INFO,
+            String::from("No items in the collection")
+        );
+        return Ok(());
+    }
+
+    for (i, child) in children.iter().enumerate() {
+        match child.collection == id {
+            true => println!("{}) {} [{}]", i, child.title, child.id),
+            false => println!("{}) {} [{}] (from {})", i, child.title, child.id, child.collection_title)
+        }
+    }
+
+    let mut selected: Vec<&Child> = vec![];
+    for idx in read_selection(data.options.get("--select"), children.len()) {
+        match children.get(idx) {
+            Some(child) if !selected.iter().any(|x| x.id == child.id) => selected.push(child),
+            Some(_) => (),
+            None => log(
+                LogLevel::ERR,
+                format!("Item with an index of '{}' does not exist", idx)
+            )
+        }
+    }
+
+    // one batch per collection so every item remembers where it came from
+    let mut groups: Vec<(ItemId, TitledItems)> = vec![];
+    for child in selected {
+        let title = Some(child.title.clone()).filter(|x| !x.is_empty());
+        match groups.iter_mut().find(|(collection, _)| *collection == child.collection) {
+            Some((_, items)) => items.push((child.id, title)),
+            None => groups.push((child.collection, vec![(child.id, title)]))
+        }
+    }
+
+    for (collection, items) in groups {
+        match data.has_flag("--with-deps") {
+            true => manager.add_items_with_deps(app_id.clone(), items, Source::Collection { id: collection }, source),
+            false => manager.add_items(app_id.clone(), items, Source::Collection { id: collection })
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches a collection and the collections in it, depth first and each
+/// only once. Returns the app id from the pages and the items of all of them.
+fn resolve(manager: &Manager, id: ItemId, source: MetadataSource) -> Result<(String, Vec<Child>), ErrorKind> {
+    let root = fetch(manager, id, source)?;
+
+    let mut app_id = root.app_id.clone();
+    let mut children: Vec<Child> = vec![];
+    // every collection that was fetched (or failed to), so one linked from two places is only fetched once
+    let mut fetched: HashSet<ItemId> = HashSet::from([root.id]);
+    let mut pending: Vec<Collection> = vec![root];
+
+    while let Some(collection) = pending.pop() {
+        for (item, title) in collection.items.iter() {
+            if !children.iter().any(|x| x.id == *item) {
+                children.push(Child {
+                    id: *item, title: title.clone(), collection: collection.id, collection_title: collection.title.clone()
+                });
+            }
+        }
+
+        // reversed so the first linked collection is the next one popped
+        for (nested, title) in collection.collections.iter().rev() {
+            if !fetched.insert(*nested) {
+                continue;
+            }
+
+            match fetch(manager, *nested, source) {
+                Ok(nested) => {
+                    app_id = app_id.or(nested.app_id.clone());
+                    pending.push(nested);
+                },
+                Err(_) => log(
+                    LogLevel::WARN,
+                    format!("Skipping the collection {} [{}]", title, nested)
+                )
+            }
+        }
+    }
+
+    match app_id {
+        Some(app_id) => Ok((app_id, children)),
+        None => {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't find the app of the collection '{}'", id)
+            );
+            Err(ErrorKind::Usage)
+        }
+    }
+}
+
+fn fetch(manager: &Manager, id: ItemId, source: MetadataSource) -> Result<Collection, ErrorKind> {
+    match manager.fetch_collection(id, source) {
+        Ok(Some(collection)) => Ok(collection),
+        Ok(None) => {
+            log(
+                LogLevel::ERR,
+                format!("'{}' is not a workshop collection, 'info {}' shows single items", id, id)
+            );
+            Err(ErrorKind::Usage)
+        },
+        Err(err) => {
+            log(LogLevel::ERR, err);
+            Err(ErrorKind::Network)
+        }
+    }
+}
@@ -1,26 +1,71 @@
 use std::{fs::File, io::Read};
 
-use crate::{cli::InputParser, utils::{log, LogLevel}, manager::Config, commands::Command};
+use crate::{cli::InputParser, utils::{log, LogLevel, ErrorKind}, manager::{Config, Manager}, queue::{ItemId, Source}, commands::{Command, CommandSpec, ArgSpec, OptionSpec, ValueKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "convert",
+    aliases: &[],
+    args: &[
+        ArgSpec { name: "appAlias", kind: ValueKind::Alias, required: true, required_for: &[], choices: &[], description: "Alias or app id the items belong to" },
+        ArgSpec { name: "filePath", kind: ValueKind::Path, required: true, required_for: &[], choices: &[], description: "A .txt file with comma separated item ids" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--with-deps",
+            short: Some('w'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Also add the items they require, and the items those require",
+        },
+        OptionSpec {
+            name: "--source",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "page or api, where --with-deps reads required items from, 'metadata_source' in the config by default",
+        },
+    ],
+    description: "Parses a file of item ids and adds them to the download list",
+    examples: &["convert rimworld mods.txt", "convert rimworld mods.txt --with-deps", "convert rimworld mods.txt --with-deps --source api"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let file = data.args[1].clone();
+    let with_deps = data.has_flag("--with-deps");
+    let metadata = manager.metadata_source(&data)?;
+    let (app_id, items) = ConvertCommand::new(&mut manager.config, data).run()?;
+    let items = items.into_iter().map(|id| (id, None)).collect();
+
+    match with_deps {
+        true => manager.add_items_with_deps(app_id, items, Source::Convert { file }, metadata),
+        false => manager.add_items(app_id, items, Source::Convert { file })
+    }
+
+    Ok(())
+}
 
 pub struct ConvertCommand<'a> {
     pub data: InputParser,
     pub config: &'a mut Config
 }
 
-impl<'a> Command<'a, (String, Vec<usize>)> for ConvertCommand<'a> {
+impl<'a> Command<'a, (String, Vec<ItemId>)> for ConvertCommand<'a> {
     fn new(config: &'a mut Config, data:  InputParser) -> Self {
         ConvertCommand { data, config }
     }
 
-    fn run(&mut self) -> (String, Vec<usize>) {
-        let assertion = self.assert();
-        if assertion.is_err() {
-            log(
-                LogLevel::ERR,
-                format!("{}", assertion.unwrap_err())
-            );
-            return (String::new(), vec![]);
-        }
+    fn run(&mut self) -> Result<(String, Vec<ItemId>), ErrorKind> {
+        let app_id = match self.try_get_app_id() {
+            Some(app_id) => app_id,
+            None => {
+                log(
+                    LogLevel::ERR, 
+                    format!("App id not found for '{}'", self.data.args[0])
+                );
+                return Err(ErrorKind::MissingAlias);
+            }
+        };
         
         let file = File::open(self.data.args[1].clone());
         if file.is_err() {
@@ -28,37 +73,51 @@ impl<'a> Command<'a, (String, Vec<usize>)> for ConvertCommand<'a> {
                 LogLevel::ERR,
                 format!("File with path {} does not exist", self.data.args[1])
             );
-            return (String::new(), vec![]);
+            return Err(ErrorKind::Io);
         }
 
         let mut buf = String::new();
-        file.unwrap().read_to_string(&mut buf);
+        if file.unwrap().read_to_string(&mut buf).is_err() {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't read file with path {}", self.data.args[1])
+            );
+            return Err(ErrorKind::Io);
+        }
 
-        let parsed: Vec<usize> = match self.data.args[1].split(".").last().unwrap() {
-            "txt" => buf.split(",").map(|x| x.to_string().parse::<usize>().unwrap())
-                                .collect::<Vec<usize>>(),
+        let parsed: Vec<ItemId> = match self.data.args[1].split(".").last().unwrap() {
+            "txt" => buf.split(',').map(|x| x.trim()).filter(|x| !x.is_empty())
+                                .filter_map(|x| match x.parse::<ItemId>() {
+                                    Ok(id) => Some(id),
+                                    Err(_) => {
+                                        log(LogLevel::WARN, format!("Skipping invalid item id '{}'", x));
+                                        None
+                                    }
+                                })
+                                .collect::<Vec<ItemId>>(),
             _ => {
                 log(
                     LogLevel::ERR,
                     format!("File with type {} not supported", self.data.args[1])
                 );
 
-                vec![]
+                return Err(ErrorKind::Usage);
             }
         };
 
-        return (self.data.args[0].clone(), parsed);
+        Ok((app_id, parsed))
     }
+}
 
-    fn assert(&self) -> Result<(), String> {
-        if self.data.args.len() == 0 {
-            return Err(String::from("Insufficient arguments"))
+impl<'a> ConvertCommand<'a> {
+    fn try_get_app_id(&self) -> Option<String> {
+        if self.data.args[0].chars().all(char::is_numeric) {
+            return Some(self.data.args[0].to_owned());
         }
 
-        Ok(())
+        match &self.config.properties {
+            Some(props) => props.get_app_id_by_name(self.data.args[0].to_owned()),
+            None => None
+        }
     }
 }
-
-impl<'a> ConvertCommand<'a> {
- 
-}
\ No newline at end of file
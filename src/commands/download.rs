@@ -0,0 +1,100 @@
+use crate::{cli::InputParser, manager::Manager, commands::{CommandSpec, OptionSpec, ValueKind}, utils::ErrorKind};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "download",
+    aliases: &["dl"],
+    args: &[],
+    options: &[
+        OptionSpec {
+            name: "--file",
+            short: Some('f'),
+            kind: ValueKind::Path,
+            default: None,
+            description: "Previously exported file to download instead of the added items",
+        },
+        OptionSpec {
+            name: "--status",
+            short: None,
+            kind: ValueKind::Text,
+            default: Some("pending,failed,cancelled"),
+            description: "Only items with these statuses (comma separated), or 'all'",
+        },
+        OptionSpec {
+            name: "--batch-size",
+            short: Some('b'),
+            kind: ValueKind::Number,
+            default: None,
+            description: "Items per steamcmd run, 'batch_size' in the config by default",
+        },
+        OptionSpec {
+            name: "--retries",
+            short: Some('r'),
+            kind: ValueKind::Number,
+            default: None,
+            description: "Times failed items are retried, 'retries' in the config by default",
+        },
+        OptionSpec {
+            name: "--jobs",
+            short: Some('j'),
+            kind: ValueKind::Number,
+            default: None,
+            description: "steamcmd processes running at the same time, 'jobs' in the config by default",
+        },
+        OptionSpec {
+            name: "--timeout",
+            short: Some('t'),
+            kind: ValueKind::Number,
+            default: None,
+            description: "Seconds without output before steamcmd is killed, 0 to wait forever, 'watchdog' in the config by default",
+        },
+        OptionSpec {
+            name: "--account",
+            short: Some('a'),
+            kind: ValueKind::Account,
+            default: None,
+            description: "Log in with this account instead of the account of each alias",
+        },
+        OptionSpec {
+            name: "--install-dir",
+            short: Some('i'),
+            kind: ValueKind::Path,
+            default: None,
+            description: "Install into this dir instead of the install dir of each alias (steamcmd's force_install_dir)",
+        },
+        OptionSpec {
+            name: "--downloader",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "steamcmd, depotdownloader or mock, 'downloader' in the config by default",
+        },
+        OptionSpec {
+            name: "--steamcmd",
+            short: None,
+            kind: ValueKind::Path,
+            default: None,
+            description: "steamcmd executable, overrides RSW_STEAMCMD and 'steamcmd' in the config",
+        },
+        OptionSpec {
+            name: "--log",
+            short: Some('l'),
+            kind: ValueKind::Path,
+            default: None,
+            description: "Append steamcmd's raw output to this file",
+        },
+        OptionSpec {
+            name: "--dry-run",
+            short: None,
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Print the commands that would run and where items go, without running them",
+        },
+    ],
+    description: "Downloads items via steamcmd or another downloader (exports automatically)",
+    examples: &["download", "download --status failed", "download --batch-size 20 --retries 3", "download --jobs 4", "download --account main", "download --install-dir /srv/arma3", "download --downloader depotdownloader", "download --log steamcmd.log", "download --dry-run", "download --file rimworld-x1y2z3w4.txt"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    manager.download(data)
+}
@@ -0,0 +1,23 @@
+use std::process;
+
+use crate::{cli::InputParser, manager::Manager, commands::CommandSpec, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "exit",
+    aliases: &["quit"],
+    args: &[],
+    options: &[],
+    description: "Saves current data and exits",
+    examples: &["exit"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, _data: InputParser) -> Result<(), ErrorKind> {
+    log(
+        LogLevel::EXIT, 
+        String::from("Saving and exitting...")
+    );
+    manager.save();
+    
+    process::exit(0);
+}
@@ -0,0 +1,85 @@
+use crate::{cli::InputParser, manager::Manager, commands::{CommandSpec, OptionSpec, ValueKind}, export::{ExportFormat, ExportOptions}, queue::StatusFilter, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "export",
+    aliases: &[],
+    args: &[],
+    options: &[
+        OptionSpec {
+            name: "--format",
+            short: Some('f'),
+            kind: ValueKind::Text,
+            default: Some("steamcmd-args"),
+            description: "steamcmd-args, runscript, json, csv or ids",
+        },
+        OptionSpec {
+            name: "--out",
+            short: Some('o'),
+            kind: ValueKind::Path,
+            default: None,
+            description: "File to write, named after the apps when not given",
+        },
+        OptionSpec {
+            name: "--random-name",
+            short: None,
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Add a random suffix to the generated file name",
+        },
+        OptionSpec {
+            name: "--status",
+            short: None,
+            kind: ValueKind::Text,
+            default: Some("pending,failed,cancelled"),
+            description: "Only items with these statuses (comma separated), or 'all'",
+        },
+        OptionSpec {
+            name: "--account",
+            short: Some('a'),
+            kind: ValueKind::Account,
+            default: None,
+            description: "Log in with this account instead of the account of each alias",
+        },
+        OptionSpec {
+            name: "--install-dir",
+            short: Some('i'),
+            kind: ValueKind::Path,
+            default: None,
+            description: "Install into this dir instead of the install dir of each alias (steamcmd's force_install_dir)",
+        },
+        OptionSpec {
+            name: "--dry-run",
+            short: None,
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Print what would be written instead of writing it",
+        },
+    ],
+    description: "Exports the queued items for steamcmd or as a manifest",
+    examples: &["export", "export --format runscript --out modpack.txt", "export --format json --status all", "export --install-dir /srv/arma3", "export --format runscript --dry-run"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let filter = data.options["--status"].parse::<StatusFilter>();
+    let format = data.options["--format"].parse::<ExportFormat>();
+
+    match (filter, format) {
+        (Ok(filter), Ok(format)) => {
+            manager.export(ExportOptions {
+                filter,
+                format,
+                out: data.options.get("--out"),
+                random_name: data.has_flag("--random-name"),
+                account: data.options.get("--account"),
+                install_dir: data.options.get("--install-dir"),
+                dry_run: data.has_flag("--dry-run"),
+            })?;
+            Ok(())
+        },
+        (Err(err), _) | (_, Err(err)) => {
+            log(LogLevel::ERR, err);
+            Err(ErrorKind::Usage)
+        }
+    }
+}
@@ -1,62 +1,122 @@
 use crate::cli::InputParser;
-use crate::commands::Command;
-use crate::manager::Config;
+use crate::commands::{self, CommandSpec, ArgSpec, ValueKind};
+use crate::manager::Manager;
+use crate::utils::{log, LogLevel, ErrorKind};
 
-pub struct HelpCommand<'a> {
-    pub data: InputParser,
-    pub config: &'a mut Config
-}
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "help",
+    aliases: &["?"],
+    args: &[
+        ArgSpec { name: "command", kind: ValueKind::Text, required: false, required_for: &[], choices: &[], description: "Show the details of a single command" },
+    ],
+    options: &[],
+    description: "Lists commands, or the details of one",
+    examples: &["help", "help search"],
+    handler: handle,
+};
 
-impl<'a> Command<'a, ()> for HelpCommand<'a> {
-    fn new(config: &'a mut Config, data: InputParser) -> Self {
-        HelpCommand { data, config }
+fn handle(_manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    match data.args.first() {
+        Some(name) => match commands::find(name) {
+            Some(spec) => display_command(spec),
+            None => {
+                log(
+                    LogLevel::ERR,
+                    format!("'{}' is not a valid command", name)
+                );
+                return Err(ErrorKind::Usage);
+            }
+        },
+        None => display_commands()
     }
 
-    // WIP:
-    // Store help commands in a hashmap and iterate through it instead of manually coding it in
-    fn run(&mut self) {
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "Command", "Args", "Options", "Description"
-        );
+    Ok(())
+}
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "help", "-", "-", "-"
-        );
+fn display_commands() {
+    println!(
+        "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
+        "Command", "Args", "Options", "Description"
+    );
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "aliases", "show, add [name] [appID], remove [name]", "-", "-"
-        );
+    for spec in commands::REGISTRY.iter() {
+        let args: Vec<String> = spec.args.iter().map(|arg| format!("[{}]", arg.name)).collect();
+        let options: Vec<&str> = spec.options.iter().map(|option| option.name).collect();
 
         println!(
             "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "search", "[appAlias] [query]", "--pages [number]", "Search workshop items (sorted by top)"
+            spec.name,
+            if args.is_empty() { String::from("-") } else { args.join(" ") },
+            if options.is_empty() { String::from("-") } else { options.join(", ") },
+            spec.description
         );
+    }
+}
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "export", "-", "-", "Exports all added items to a valid steamcmd command in txt"
-        );
+fn display_command(spec: &CommandSpec) {
+    println!("{}", spec.description);
+    println!();
+    println!("Usage: {}", spec.usage());
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "convert", "[appAlias] [filePath]", "--file", "Parses a file and converts it to the valid format"
-        );
+    if !spec.aliases.is_empty() {
+        println!("Aliases: {}", spec.aliases.join(", "));
+    }
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "download", "-", "--file", "Downloads items via steamcmd (exports automatically)"
-        );
+    if !spec.args.is_empty() {
+        println!();
+        println!("Args:");
 
-        println!(
-            "{0: <30} | {1: <30} | {2: <30} | {3: <30}",
-            "exit", "-", "-", "Saves current data and exits"
-        );
+        for arg in spec.args.iter() {
+            let required = match (arg.required, arg.required_for.is_empty()) {
+                (true, _) => String::new(),
+                (false, true) => String::from(" (optional)"),
+                (false, false) => format!(" (needed for {})", arg.required_for.join(", "))
+            };
+
+            println!(
+                "  {0: <20} {1}{2}",
+                arg.name,
+                arg.description,
+                required
+            );
+        }
+    }
+
+    if !spec.options.is_empty() {
+        println!();
+        println!("Options:");
+
+        for option in spec.options.iter() {
+            let name = match option.short {
+                Some(short) => format!("-{}, {}", short, option.name),
+                None => format!("    {}", option.name)
+            };
+            let value = match option.kind {
+                ValueKind::Flag => "",
+                ValueKind::Text => " [text]",
+                ValueKind::Number => " [number]",
+                ValueKind::Path => " [path]",
+                ValueKind::Alias => " [alias]",
+                ValueKind::Account => " [account]",
+            };
+            let default = match option.default {
+                Some(default) => format!(" (default: {})", default),
+                None => String::new()
+            };
+
+            println!(
+                "  {0: <30} {1}{2}",
+                format!("{}{}", name, value), option.description, default
+            );
+        }
     }
 
-    fn assert(&self) -> Result<(), String> {
-        Ok(())
+    if !spec.examples.is_empty() {
+        println!();
+        println!("Examples:");
+
+        for example in spec.examples.iter() {
+            println!("  {}", example);
+        }
     }
 }
@@ -0,0 +1,80 @@
+use crate::{cli::InputParser, manager::Manager, commands::{CommandSpec, ArgSpec, OptionSpec, ValueKind}, export, queue::{Source, TitledItems}, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "import",
+    aliases: &[],
+    args: &[
+        ArgSpec { name: "filePath", kind: ValueKind::Path, required: true, required_for: &[], choices: &[], description: "A steamcmd-args, runscript or json export" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--with-deps",
+            short: Some('w'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Also add the items they require, and the items those require",
+        },
+        OptionSpec {
+            name: "--source",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "page or api, where --with-deps reads required items from, 'metadata_source' in the config by default",
+        },
+    ],
+    description: "Adds the items of a previous export back to the queue",
+    examples: &["import rimworld+terraria.txt", "import modpack.runscript.txt", "import modpack.json", "import modpack.json --with-deps", "import modpack.json --with-deps --source api"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let path = &data.args[0];
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            log(
+                LogLevel::ERR, 
+                format!("File with the path '{}' does not exist", path)
+            );
+            return Err(ErrorKind::Io);
+        }
+    };
+
+    let items = match export::parse_export(&contents) {
+        Ok(items) => items,
+        Err(err) => {
+            log(
+                LogLevel::ERR, 
+                format!("Couldn't import '{}': {}", path, err)
+            );
+            return Err(ErrorKind::Usage);
+        }
+    };
+
+    if items.is_empty() {
+        log(
+            LogLevel::WARN, 
+            format!("No workshop items found in '{}'", path)
+        );
+        return Ok(());
+    }
+
+    // keep the order of the file, one batch per app
+    let mut apps: Vec<(String, TitledItems)> = vec![];
+    for item in items {
+        match apps.iter_mut().find(|(id, _)| *id == item.app_id) {
+            Some((_, app_items)) => app_items.push((item.item_id, item.title)),
+            None => apps.push((item.app_id, vec![(item.item_id, item.title)]))
+        }
+    }
+
+    let metadata = manager.metadata_source(&data)?;
+    for (app_id, app_items) in apps {
+        match data.has_flag("--with-deps") {
+            true => manager.add_items_with_deps(app_id, app_items, Source::Import { file: path.clone() }, metadata),
+            false => manager.add_items(app_id, app_items, Source::Import { file: path.clone() })
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::{cli::InputParser, manager::Manager, commands::{CommandSpec, ArgSpec, OptionSpec, ValueKind}, workshop::{self, ItemDetails}, utils::{log, LogLevel, ErrorKind}};
+
+/// Characters of the description shown before it is cut off
+const DESCRIPTION_EXCERPT: usize = 300;
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "info",
+    aliases: &["i"],
+    args: &[
+        ArgSpec { name: "item", kind: ValueKind::Text, required: true, required_for: &[], choices: &[], description: "Item id or link to the item page" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--refresh",
+            short: Some('r'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Fetch the item page again instead of using the cache",
+        },
+        OptionSpec {
+            name: "--source",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "page or api, 'metadata_source' in the config by default",
+        },
+    ],
+    description: "Shows the details of a workshop item",
+    examples: &["info 2009463077", "info https://steamcommunity.com/sharedfiles/filedetails/?id=2009463077", "info 2009463077 --refresh", "info 2009463077 --source api"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let id = match workshop::parse_id(&data.args[0]) {
+        Some(id) => id,
+        None => {
+            log(
+                LogLevel::ERR,
+                format!("'{}' is not an item id or a link to an item", data.args[0])
+            );
+            return Err(ErrorKind::Usage);
+        }
+    };
+
+    let source = manager.metadata_source(&data)?;
+    let details = manager.item_details(id, data.has_flag("--refresh"), source)?;
+    print_details(&details);
+
+    Ok(())
+}
+
+fn print_details(details: &ItemDetails) {
+    let unknown = String::from("-");
+    let rows = [
+        ("App", details.app_id.clone().unwrap_or(unknown.clone())),
+        ("Author", details.author.clone().unwrap_or(unknown.clone())),
+        ("Size", details.size.clone().unwrap_or(unknown.clone())),
+        ("Posted", details.posted.clone().unwrap_or(unknown.clone())),
+        ("Updated", details.updated.clone().unwrap_or(unknown.clone())),
+        ("Tags", match details.tags.is_empty() {
+            true => unknown.clone(),
+            false => details.tags.join(", ")
+        }),
+        ("Rating", details.rating.clone().unwrap_or(unknown.clone())),
+        ("Subscribers", details.subscribers.map_or(unknown.clone(), |x| x.to_string())),
+    ];
+
+    println!("{}", "-".repeat(60));
+    println!("{}", details.display_title());
+    println!("{}", "-".repeat(60));
+
+    for (label, value) in rows {
+        println!("{0: <12} | {1}", label, value);
+    }
+
+    if !details.description.is_empty() {
+        let mut excerpt: String = details.description.chars().take(DESCRIPTION_EXCERPT).collect();
+        if excerpt.len() < details.description.len() {
+            excerpt.push_str(" ...");
+        }
+
+        println!("{}", "-".repeat(60));
+        println!("{}", excerpt);
+    }
+
+    if !details.required_items.is_empty() {
+        println!("{}", "-".repeat(60));
+        println!("Required items:");
+        for item in details.required_items.iter() {
+            println!("  {} [{}]", item.title, item.id);
+        }
+    }
+
+    if !details.required_dlc.is_empty() {
+        println!("{}", "-".repeat(60));
+        println!("Required DLC:");
+        for dlc in details.required_dlc.iter() {
+            println!("  {} ({})", dlc.name, dlc.app_id);
+        }
+    }
+
+    println!("{}", "-".repeat(60));
+}
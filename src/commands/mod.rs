@@ -1,12 +1,257 @@
-use crate::{manager::Config, cli::InputParser};
+use std::io::{self, Write};
+
+use crate::{manager::{Config, Manager}, cli::{InputParser, ParseError}, utils::{log, LogLevel, ErrorKind}};
 
 pub trait Command<'a, Run> {
     fn new(config: &'a mut Config, data: InputParser) -> Self;
-    fn run(&mut self) -> Run;
-    fn assert(&self) -> Result<(), String>; 
+    fn run(&mut self) -> Result<Run, ErrorKind>;
 }
 
 pub mod alias;
+pub mod account;
 pub mod help;
 pub mod search;
-pub mod convert;
\ No newline at end of file
+pub mod convert;
+pub mod export;
+pub mod download;
+pub mod run;
+pub mod exit;
+pub mod queue;
+pub mod import;
+pub mod info;
+pub mod collection;
+
+// ==========================
+// Specs
+// ==========================
+pub type Handler = fn(&mut Manager, InputParser) -> Result<(), ErrorKind>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// Takes no value, only its presence matters
+    Flag,
+    Text,
+    Number,
+    Path,
+    /// Name of an app alias from the config
+    Alias,
+    /// Name of a steam account from the config
+    Account,
+}
+
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ValueKind,
+    pub required: bool,
+    /// Values of the first arg (the action) that need this arg even though it's optional otherwise
+    pub required_for: &'static [&'static str],
+    /// When not empty the arg must be one of these, used for subcommands
+    pub choices: &'static [&'static str],
+    pub description: &'static str,
+}
+
+pub struct OptionSpec {
+    /// Long name including the dashes, e.g. `--pages`
+    pub name: &'static str,
+    pub short: Option<char>,
+    pub kind: ValueKind,
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+/// Everything dispatch, validation and `help` need to know about a command
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [ArgSpec],
+    pub options: &'static [OptionSpec],
+    pub description: &'static str,
+    pub examples: &'static [&'static str],
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    /// Checks positional args and option values, then fills in option defaults
+    pub fn validate(&self, data: &mut InputParser) -> Result<(), String> {
+        for (i, arg) in self.args.iter().enumerate() {
+            match data.args.get(i) {
+                Some(value) if !arg.choices.is_empty() && !arg.choices.contains(&value.as_str()) => {
+                    return Err(format!(
+                        "'{}' is not a valid {}, expected one of: {}",
+                        value, arg.name, arg.choices.join(", ")
+                    ));
+                },
+                Some(value) if arg.kind == ValueKind::Number && value.parse::<i64>().is_err() => {
+                    return Err(format!("'{}' expects a number, got '{}'", arg.name, value));
+                },
+                Some(_) => (),
+                None if arg.required => return Err(format!("Missing argument [{}]", arg.name)),
+                None => match data.args.first() {
+                    Some(action) if arg.required_for.contains(&action.as_str()) => {
+                        return Err(format!("Missing argument [{}] for '{}'", arg.name, action));
+                    },
+                    _ => ()
+                }
+            }
+        }
+
+        for option in self.options.iter() {
+            match data.options.get(option.name) {
+                Some(value) if option.kind == ValueKind::Number && value.parse::<i64>().is_err() => {
+                    return Err(format!("'{}' expects a number, got '{}'", option.name, value));
+                },
+                Some(_) => (),
+                None => if let Some(default) = option.default {
+                    data.options.insert(option.name.to_string(), default.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = vec![self.name.to_string()];
+
+        for arg in self.args.iter() {
+            match arg.required {
+                true => usage.push(format!("[{}]", arg.name)),
+                false => usage.push(format!("({})", arg.name))
+            }
+        }
+
+        usage.join(" ")
+    }
+}
+
+/// Uses `selection` when given (either `all` or space separated indices),
+/// otherwise asks for the indices on stdin.
+pub fn read_selection(selection: Option<&String>, items_len: usize) -> Vec<usize> {
+    let buf = match selection {
+        Some(selection) => selection.to_owned(),
+        None => {
+            let mut buf = String::new();
+
+            print!("Select by index (0 1 2) > ");
+            io::stdout().flush().unwrap();
+            io::stdin().read_line(&mut buf).unwrap();
+
+            buf
+        }
+    };
+
+    if buf.trim() == "all" {
+        return (0..items_len).collect();
+    }
+
+    let mut indices: Vec<usize> = vec![];
+    for token in buf.split_whitespace() {
+        match token.parse::<usize>() {
+            Ok(index) => indices.push(index),
+            Err(_) => log(
+                LogLevel::ERR,
+                format!("'{}' is not a valid index", token)
+            )
+        }
+    }
+
+    indices
+}
+
+// ==========================
+// Registry
+// ==========================
+pub const REGISTRY: &[&CommandSpec] = &[
+    &help::SPEC,
+    &alias::SPEC,
+    &account::SPEC,
+    &search::SPEC,
+    &info::SPEC,
+    &collection::SPEC,
+    &convert::SPEC,
+    &export::SPEC,
+    &import::SPEC,
+    &download::SPEC,
+    &queue::SPEC,
+    &run::SPEC,
+    &exit::SPEC,
+];
+
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    REGISTRY.iter()
+        .find(|spec| spec.name == name || spec.aliases.contains(&name))
+        .copied()
+}
+
+/// Parses and runs a single line, an empty line is a no-op.
+pub fn execute_line(manager: &mut Manager, line: String) -> Result<(), ErrorKind> {
+    match InputParser::tokenize(&line) {
+        Ok(tokens) if tokens.is_empty() => Ok(()),
+        Ok(tokens) => execute(manager, tokens),
+        Err(err) => {
+            log(LogLevel::ERR, err.to_string());
+            Err(ErrorKind::Usage)
+        }
+    }
+}
+
+/// Looks up the command named by the first token and runs it with the rest
+pub fn execute(manager: &mut Manager, tokens: Vec<String>) -> Result<(), ErrorKind> {
+    let spec = match tokens.first().and_then(|name| find(name)) {
+        Some(spec) => spec,
+        None => {
+            log(
+                LogLevel::ERR,
+                format!("'{}' is not a valid command", tokens.first().map_or("", |x| x.as_str()))
+            );
+            return Err(ErrorKind::Usage);
+        }
+    };
+
+    let mut input_parser = match InputParser::from_tokens(tokens, spec.options) {
+        Ok(input_parser) => input_parser,
+        Err(ParseError::Empty) => return Ok(()),
+        Err(err) => {
+            log(LogLevel::ERR, err.to_string());
+            return Err(ErrorKind::Usage);
+        }
+    };
+    input_parser.command = spec.name.to_string();
+
+    if let Err(err) = spec.validate(&mut input_parser) {
+        log(LogLevel::ERR, err);
+        log(LogLevel::INFO, format!("Usage: {} (see 'help {}')", spec.usage(), spec.name));
+        return Err(ErrorKind::Usage);
+    }
+
+    (spec.handler)(manager, input_parser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(line: &str) -> Result<(), String> {
+        let spec = find(&InputParser::tokenize(line).unwrap()[0]).unwrap();
+        let mut data = InputParser::from_tokens(InputParser::tokenize(line).unwrap(), spec.options).unwrap();
+        spec.validate(&mut data)
+    }
+
+    #[test]
+    fn positional_numbers() {
+        assert_eq!(validate("queue move 111 abc"), Err(String::from("'position' expects a number, got 'abc'")));
+        assert_eq!(validate("queue move 111 2"), Ok(()));
+    }
+
+    #[test]
+    fn args_required_for_an_action() {
+        assert_eq!(validate("queue move 111"), Err(String::from("Missing argument [position] for 'move'")));
+        assert_eq!(validate("queue remove"), Err(String::from("Missing argument [item] for 'remove'")));
+        assert_eq!(validate("aliases set rimworld"), Err(String::from("Missing argument [value] for 'set'")));
+        assert_eq!(validate("accounts use"), Err(String::from("Missing argument [name] for 'use'")));
+
+        assert_eq!(validate("queue clear"), Ok(()));
+        assert_eq!(validate("aliases dir arma3"), Ok(()));
+        assert_eq!(validate("accounts use arma3"), Ok(()));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{cli::InputParser, manager::Manager, commands::{CommandSpec, ArgSpec, OptionSpec, ValueKind}, queue::StatusFilter, utils::{log, LogLevel, ErrorKind, format_timestamp}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "queue",
+    aliases: &["q"],
+    args: &[
+        ArgSpec {
+            name: "action",
+            kind: ValueKind::Text,
+            required: true,
+            required_for: &[],
+            choices: &["show", "remove", "clear", "move"],
+            description: "show, remove [item], clear (app) or move [item] [position]",
+        },
+        ArgSpec { name: "item", kind: ValueKind::Text, required: false, required_for: &["remove", "move"], choices: &[], description: "Published file id or index from 'queue show', or an app for clear" },
+        ArgSpec { name: "position", kind: ValueKind::Number, required: false, required_for: &["move"], choices: &[], description: "New position of the item within its app" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--status",
+            short: None,
+            kind: ValueKind::Text,
+            default: Some("all"),
+            description: "Only show items with these statuses (comma separated)",
+        },
+    ],
+    description: "Shows and edits the items waiting to be exported or downloaded",
+    examples: &["queue show", "queue show --status failed", "queue remove 3", "queue remove 2847191234", "queue clear rimworld", "queue move 5 0"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    match data.args[0].as_str() {
+        "show" => show(manager, &data),
+        "remove" => remove(manager, &data.args[1]),
+        "clear" => clear(manager, data.args.get(1)),
+        "move" => match data.args[2].parse::<usize>() {
+            Ok(position) => move_item(manager, &data.args[1], position),
+            Err(_) => {
+                log(
+                    LogLevel::ERR,
+                    format!("position must be 0 or more, got '{}'", data.args[2])
+                );
+                Err(ErrorKind::Usage)
+            }
+        },
+        _ => unreachable!()
+    }
+}
+
+fn show(manager: &Manager, data: &InputParser) -> Result<(), ErrorKind> {
+    let filter: StatusFilter = match data.options["--status"].parse() {
+        Ok(filter) => filter,
+        Err(err) => {
+            log(LogLevel::ERR, err);
+            return Err(ErrorKind::Usage);
+        }
+    };
+
+    if manager.queue.is_empty() {
+        log(
+            LogLevel::INFO,
+            String::from("No items in the queue")
+        );
+        return Ok(());
+    }
+
+    let mut current_app = String::new();
+    for (i, position) in manager.queue.ordered().into_iter().enumerate() {
+        let entry = &manager.queue.entries[position];
+        if !filter.matches(&entry.status) {
+            continue;
+        }
+
+        if entry.app_id != current_app {
+            println!("{}", "-".repeat(60));
+            println!("{} ({})", entry.app_name, entry.app_id);
+            println!("{}", "-".repeat(60));
+
+            current_app = entry.app_id.clone();
+        }
+
+        println!("{}) {}", i, entry.display_title());
+        println!(
+            "   {} | from {} | added {}",
+            entry.status, entry.source, format_timestamp(entry.added_at)
+        );
+    }
+
+    Ok(())
+}
+
+fn remove(manager: &mut Manager, key: &str) -> Result<(), ErrorKind> {
+    let position = match manager.queue.find(key) {
+        Some(position) => position,
+        None => return not_found(key)
+    };
+
+    let entry = manager.queue.remove(position);
+    manager.save_queue();
+
+    log(
+        LogLevel::WARN,
+        format!("Removed {} from the queue", entry.display_title())
+    );
+
+    Ok(())
+}
+
+/// Clears the whole queue, or only the items of one app (by alias or app id)
+fn clear(manager: &mut Manager, app: Option<&String>) -> Result<(), ErrorKind> {
+    match app {
+        Some(app) => {
+            let app_id = manager.config.get_props_ref()
+                .and_then(|props| props.get_app_id_by_name(app.clone()))
+                .unwrap_or(app.clone());
+
+            match manager.queue.clear_app(&app_id) {
+                0 => {
+                    log(
+                        LogLevel::ERR,
+                        format!("No queued items for '{}'", app)
+                    );
+                    return Err(ErrorKind::MissingAlias);
+                },
+                removed => log(
+                    LogLevel::WARN,
+                    format!("Removed {} items for '{}'", removed, app)
+                )
+            }
+        },
+        None => {
+            manager.queue.entries.clear();
+
+            log(
+                LogLevel::WARN,
+                String::from("Cleared the queue")
+            );
+        }
+    }
+
+    manager.save_queue();
+    Ok(())
+}
+
+/// Moves an item within the items of its app, which is the order steamcmd
+/// downloads them in
+fn move_item(manager: &mut Manager, key: &str, position: usize) -> Result<(), ErrorKind> {
+    let from = match manager.queue.find(key) {
+        Some(from) => from,
+        None => return not_found(key)
+    };
+
+    let title = manager.queue.entries[from].display_title();
+    let position = manager.queue.move_within_app(from, position);
+    manager.save_queue();
+
+    log(
+        LogLevel::SUCCESS,
+        format!("Moved {} to position {}", title, position)
+    );
+
+    Ok(())
+}
+
+fn not_found(key: &str) -> Result<(), ErrorKind> {
+    log(
+        LogLevel::ERR,
+        format!("No queued item with an id or index of '{}'", key)
+    );
+
+    Err(ErrorKind::Usage)
+}
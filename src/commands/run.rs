@@ -0,0 +1,92 @@
+use std::{cell::RefCell, fs, path::PathBuf};
+
+use crate::{cli::InputParser, manager::Manager, commands::{self, CommandSpec, ArgSpec, OptionSpec, ValueKind}, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "run",
+    aliases: &[],
+    args: &[
+        ArgSpec { name: "filePath", kind: ValueKind::Path, required: true, required_for: &[], choices: &[], description: "Script with one command per line" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--keep-going",
+            short: Some('k'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Run the remaining lines after a command fails",
+        },
+    ],
+    description: "Runs each line of a script file as a command",
+    examples: &["run modpack.rsw", "run modpack.rsw --keep-going"],
+    handler: handle,
+};
+
+thread_local! {
+    /// Scripts that are running right now, the innermost last
+    static RUNNING: RefCell<Vec<PathBuf>> = const { RefCell::new(vec![]) };
+}
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    run_script(manager, &data.args[0], data.has_flag("--keep-going"))
+}
+
+/// Runs every line of a script file as a command. Empty lines and lines
+/// starting with `#` are skipped.
+fn run_script(manager: &mut Manager, path: &str, keep_going: bool) -> Result<(), ErrorKind> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            log(
+                LogLevel::ERR, 
+                format!("File with the path '{}' does not exist", path)
+            );
+            return Err(ErrorKind::Io);
+        }
+    };
+
+    // a script running itself, directly or through others, would never end
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    if RUNNING.with(|x| x.borrow().contains(&canonical)) {
+        log(
+            LogLevel::ERR,
+            format!("'{}' is already running, scripts can't run themselves", path)
+        );
+        return Err(ErrorKind::Usage);
+    }
+
+    RUNNING.with(|x| x.borrow_mut().push(canonical));
+    let result = run_lines(manager, path, &contents, keep_going);
+    RUNNING.with(|x| x.borrow_mut().pop());
+
+    result
+}
+
+fn run_lines(manager: &mut Manager, path: &str, contents: &str, keep_going: bool) -> Result<(), ErrorKind> {
+    let mut result = Ok(());
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        log(
+            LogLevel::INFO, 
+            format!("{}:{} > {}", path, i + 1, line)
+        );
+
+        if let Err(kind) = commands::execute_line(manager, line.to_string()) {
+            if !keep_going {
+                log(
+                    LogLevel::ERR, 
+                    format!("Stopped at line {} of '{}'", i + 1, path)
+                );
+                return Err(kind);
+            }
+
+            result = Err(kind);
+        }
+    }
+
+    result
+}
@@ -1,98 +1,112 @@
-use std::{io::{self, Write}};
-
-use reqwest::{blocking::Response};
-use scraper::{ElementRef};
+use crate::{cli::InputParser, manager::{Config, Manager}, queue::{ItemId, Source, TitledItems}, commands::{Command, CommandSpec, ArgSpec, OptionSpec, ValueKind, read_selection}, api, workshop::{self, MetadataSource}, utils::{log, LogLevel, ErrorKind}};
+
+pub const SPEC: CommandSpec = CommandSpec {
+    name: "search",
+    aliases: &["s"],
+    args: &[
+        ArgSpec { name: "appAlias", kind: ValueKind::Alias, required: true, required_for: &[], choices: &[], description: "Alias or app id to search in" },
+        ArgSpec { name: "query", kind: ValueKind::Text, required: false, required_for: &[], choices: &[], description: "Search text, quote it when it has spaces" },
+    ],
+    options: &[
+        OptionSpec {
+            name: "--pages",
+            short: Some('p'),
+            kind: ValueKind::Number,
+            default: Some("1"),
+            description: "Page of the results to show",
+        },
+        OptionSpec {
+            name: "--days",
+            short: Some('d'),
+            kind: ValueKind::Number,
+            default: Some("-1"),
+            description: "Only items from the last n days, -1 for all time",
+        },
+        OptionSpec {
+            name: "--select",
+            short: Some('s'),
+            kind: ValueKind::Text,
+            default: None,
+            description: "'all' or indices like \"0 1 2\" instead of asking",
+        },
+        OptionSpec {
+            name: "--with-deps",
+            short: Some('w'),
+            kind: ValueKind::Flag,
+            default: None,
+            description: "Also add the items they require, and the items those require",
+        },
+        OptionSpec {
+            name: "--source",
+            short: None,
+            kind: ValueKind::Text,
+            default: None,
+            description: "page or api (needs an API key), 'metadata_source' in the config by default",
+        },
+    ],
+    description: "Search workshop items (sorted by top)",
+    examples: &["search rimworld \"hospital\"", "search rimworld hospital --pages 2 --select all", "search rimworld hospitality --with-deps", "search rimworld hospitality --source api"],
+    handler: handle,
+};
+
+fn handle(manager: &mut Manager, data: InputParser) -> Result<(), ErrorKind> {
+    let query = data.args.get(1).cloned().unwrap_or_default();
+    let with_deps = data.has_flag("--with-deps");
+    let metadata = manager.metadata_source(&data)?;
+    let api = match metadata {
+        MetadataSource::Api => Some(manager.api_client()),
+        MetadataSource::Page => None
+    };
+
+    let mut command = SearchCommand::new(&mut manager.config, data);
+    command.api = api;
+    let (app_id, items) = command.run()?;
+
+    match with_deps {
+        true => manager.add_items_with_deps(app_id, items, Source::Search { query }, metadata),
+        false => manager.add_items(app_id, items, Source::Search { query })
+    }
 
-use crate::{cli::InputParser, manager::Config, commands::Command, utils::{log, LogLevel}};
+    Ok(())
+}
 
 pub struct SearchCommand<'a> {
     pub data: InputParser,
     pub config: &'a mut Config,
+    /// Searches through `QueryFiles` instead of the browse page when set
+    pub api: Option<api::Client>,
     app_id: String
 }
 
-impl<'a> Command<'a, (String, Vec<usize>)> for SearchCommand<'a> {
+impl<'a> Command<'a, (String, TitledItems)> for SearchCommand<'a> {
     fn new(config: &'a mut Config, data: InputParser) -> Self {
-        SearchCommand { data, config, app_id: String::new() }
+        SearchCommand { data, config, api: None, app_id: String::new() }
     }
 
-    fn run(&mut self) -> (String, Vec<usize>) {
-        let assertion = self.assert();
-        if assertion.is_err() {
-            log(
-                LogLevel::ERR,
-                format!("{}", assertion.unwrap_err())
-            );
-            return (String::new(), vec![]);
-        }
-
+    fn run(&mut self) -> Result<(String, TitledItems), ErrorKind> {
         let app_id_res = self.try_get_app_id();
         if app_id_res.is_none() {
             log(
                 LogLevel::ERR, 
                 format!("App id not found for '{}'", self.data.args[0])
             );
-            return (String::new(), vec![]);
+            return Err(ErrorKind::MissingAlias);
         }
         self.app_id = app_id_res.unwrap();
 
-        let url = self.create_url(1);
-
-        let req: Option<Response> = match reqwest::blocking::get(&url) {
-            Ok(res) => Some(res),
-            Err(_err) => None
+        let results = match &self.api {
+            Some(client) => self.query_api(client)?,
+            None => self.scrape()?
         };
 
-        if req.is_none() {
-            log(
-                LogLevel::ERR, 
-                format!("Couldn't access steam workshop at this time")
-            );
-            return (String::new(), vec![])
-        }
-
-        let html = scraper::Html::parse_document(&req.unwrap().text().unwrap());
-        let app_header_selector = scraper::Selector::parse(".apphub_HomeHeaderContent").unwrap();
-
-        if html.select(&app_header_selector).count() == 0 {
-            log(
-                LogLevel::ERR, 
-                format!("An app with an appID of '{}' does not exist", self.app_id.trim())
-            );
-            return (String::new(), vec![]);
-        }
-
-        if self.config.properties.is_some() {
-            let title_selector = scraper::Selector::parse("title").unwrap();
-            let title_el: ElementRef = *(html.select(&title_selector).collect::<Vec<ElementRef>>())
-                .get(0).unwrap();
-
-            self.config.properties.as_mut().unwrap().set_alias(self.app_id.clone(), title_el);
-        }
-        
-
-        let workshop_item_selector = scraper::Selector::parse(".workshopItem").unwrap();
-        let mut workshop_items: Vec<ElementRef> = html.select(&workshop_item_selector).collect();
-
-        self.display_workshop_items(&mut workshop_items);
-
-        let mut buf = String::new();
+        self.display_workshop_items(&results);
 
-        print!("Select by index (0 1 2) > ");
-        io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut buf).unwrap();
-
-        let indices: Vec<usize> = buf.split_whitespace()
-            .filter(|x| x.chars().all(char::is_numeric))
-            .map(|x| x.parse::<usize>().unwrap())
-            .collect();
-        let mut selected_ids: Vec<usize> = vec![];
+        let indices: Vec<usize> = read_selection(self.data.options.get("--select"), results.len());
+        let mut selected: TitledItems = vec![];
 
         for idx in indices {
-            match workshop_items.get(idx) {
-                Some(item) => selected_ids.push(
-                    self.get_item_id(item).parse::<usize>().unwrap()
-                ),
+            match results.get(idx) {
+                Some((id, title)) => selected.push((*id, Some(title.clone()))),
                 None => log(
                     LogLevel::ERR, 
                     format!("Item with an index of '{}' does not exist", idx)
@@ -100,27 +114,66 @@ impl<'a> Command<'a, (String, Vec<usize>)> for SearchCommand<'a> {
             }
         }
 
-        selected_ids.sort();
-        selected_ids.dedup();
+        selected.sort_by_key(|(id, _)| *id);
+        selected.dedup_by_key(|(id, _)| *id);
 
-        return (self.app_id.trim().to_string(), selected_ids)
+        Ok((self.app_id.trim().to_string(), selected))
 
     }
+}
+
+impl<'a> SearchCommand<'a> {
+    /// `(id, title)` of the items on the browse page
+    fn scrape(&mut self) -> Result<Vec<(ItemId, String)>, ErrorKind> {
+        let url = self.create_url();
+
+        let text: Option<String> = match reqwest::blocking::get(&url) {
+            Ok(res) => res.text().ok(),
+            Err(_err) => None
+        };
+
+        if text.is_none() {
+            log(
+                LogLevel::ERR, 
+                String::from("Couldn't access steam workshop at this time")
+            );
+            return Err(ErrorKind::Network);
+        }
 
-    fn assert(&self) -> Result<(), String> {
-        if self.data.args.len() == 0 {
-            return Err(String::from("Insufficient arguments"))
+        let page = match workshop::parse_browse_page(&scraper::Html::parse_document(&text.unwrap())) {
+            Some(page) => page,
+            None => {
+                log(
+                    LogLevel::ERR, 
+                    format!("An app with an appID of '{}' does not exist", self.app_id.trim())
+                );
+                return Err(ErrorKind::MissingAlias);
+            }
+        };
+
+        if let (Some(props), Some(title)) = (self.config.properties.as_mut(), page.title) {
+            props.set_alias(self.app_id.clone(), title);
         }
 
-        Ok(())
+        Ok(page.items)
     }
-}
 
-impl<'a> SearchCommand<'a> {
-    fn get_item_id(&self, workshop_item: &'a ElementRef) -> &str {
-        workshop_item.select(&scraper::Selector::parse(".ugc").unwrap())
-            .next().unwrap()
-            .value().attr("data-publishedfileid").unwrap()
+    /// `(id, title)` of the items `QueryFiles` finds, the alias isn't named from it
+    fn query_api(&self, client: &api::Client) -> Result<Vec<(ItemId, String)>, ErrorKind> {
+        let query = api::Query {
+            app_id: self.app_id.trim(),
+            text: self.data.args.get(1).map_or("", |x| x.as_str()),
+            days: self.data.options["--days"].parse().unwrap_or(-1),
+            page: self.data.options["--pages"].parse().unwrap_or(1),
+        };
+
+        match client.query_files(&query) {
+            Ok(files) => Ok(files.into_iter().map(|x| (ItemId(x.publishedfileid), x.title)).collect()),
+            Err(err) => {
+                log(LogLevel::ERR, err);
+                Err(ErrorKind::Network)
+            }
+        }
     }
 
     fn try_get_app_id(&mut self) -> Option<String> {
@@ -134,24 +187,20 @@ impl<'a> SearchCommand<'a> {
         }
     }
 
-    fn display_workshop_items(&self, items: &mut Vec<ElementRef>) {
-        for (i, workshop_item) in items.into_iter().enumerate() {
-            let item_name_selector = scraper::Selector::parse(".workshopItemTitle").unwrap();
-            let item_name: String = workshop_item.select(&item_name_selector).flat_map(|el| el.text()).collect();
-
-            let item_id = self.get_item_id(workshop_item);
-
+    fn display_workshop_items(&self, items: &[(ItemId, String)]) {
+        for (i, (item_id, item_name)) in items.iter().enumerate() {
             println!("{}) {} [{}]", i, item_name, item_id);
         }
     }
 
-    fn create_url(&self, page: usize) -> String {
+    fn create_url(&self) -> String {
         format!(
-            "https://steamcommunity.com/workshop/browse/?appid={}&searchtext={}&days={}&p={}",
+            "{}/workshop/browse/?appid={}&searchtext={}&days={}&p={}",
+            self.config.community_url(),
             self.app_id, 
-            self.data.args.get(1).or(Some(&String::from(""))).unwrap(),
-            self.data.options.get("--days").or(Some(&String::from("-1"))).unwrap(),
-            self.data.options.get("--pages").or(Some(&page.to_string())).unwrap(),
+            self.data.args.get(1).map_or("", |x| x.as_str()),
+            self.data.options["--days"],
+            self.data.options["--pages"],
         )
     }
 }
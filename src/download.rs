@@ -0,0 +1,658 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::downloader::Downloader;
+use crate::steamcmd::{self, DownloadItem, ItemResult};
+use crate::utils::format_bytes;
+
+/// Install dirs of the parallel jobs, next to `config.json`
+const JOBS_DIR: &str = "rsw-jobs";
+
+/// How often running processes are checked for cancellation and the watchdog
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set while a download is running, so Ctrl-C knows whether there is anything to cancel
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Cancels the running download, returns false if there is none
+pub fn cancel() -> bool {
+    if !RUNNING.load(Ordering::SeqCst) {
+        return false;
+    }
+
+    CANCELLED.store(true, Ordering::SeqCst);
+    true
+}
+
+pub fn cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+pub struct Options {
+    pub downloader: Arc<dyn Downloader>,
+    pub batch_size: usize,
+    pub retries: u32,
+    /// Seconds before the first retry, doubled for every retry after it
+    pub retry_delay: u64,
+    pub jobs: usize,
+    /// Seconds without output after which the downloader is killed, 0 to wait forever
+    pub watchdog: u64,
+    /// Passwords of the accounts the items log in with, by username
+    pub passwords: HashMap<String, String>,
+}
+
+/// What the jobs report back while downloading
+pub enum Event {
+    /// A job started downloading `items` items
+    Started { job: usize, items: usize, attempt: u32 },
+    /// A line the downloader wrote to stdout or stderr
+    Output { job: usize, line: String },
+    /// The downloader is waiting for input, e.g. a Steam Guard code. The answer is sent to `reply`.
+    Prompt { job: usize, text: String, reply: Sender<String> },
+    /// The result of an item after an attempt. `last` is set unless it is going to be retried.
+    Finished { job: usize, item: DownloadItem, result: ItemResult, attempt: u32, last: bool },
+    Retrying { job: usize, items: usize, delay: u64, attempt: u32 },
+    /// The downloader couldn't be started, no more batches are downloaded
+    Aborted { reason: String },
+}
+
+/// The events of a running download, ends once every job is done
+pub struct Download {
+    events: Receiver<Event>
+}
+
+impl Iterator for Download {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Splits `items` into batches and downloads them on `options.jobs` threads,
+/// each running its own downloader process. Batches that haven't started when the
+/// download is cancelled are left out of the events.
+pub fn start(items: Vec<DownloadItem>, options: Options) -> Download {
+    let (sender, receiver) = mpsc::channel();
+
+    CANCELLED.store(false, Ordering::SeqCst);
+    RUNNING.store(true, Ordering::SeqCst);
+
+    let (batches, jobs) = plan(&items, &options);
+
+    let batches = Arc::new(Mutex::new(VecDeque::from(batches)));
+    let aborted = Arc::new(AtomicBool::new(false));
+    let options = Arc::new(options);
+
+    for job in 1..=jobs {
+        let (batches, aborted, options, sender) = (batches.clone(), aborted.clone(), options.clone(), sender.clone());
+
+        thread::spawn(move || {
+            loop {
+                let batch = match batches.lock().unwrap().pop_front() {
+                    Some(batch) => batch,
+                    None => break
+                };
+
+                if aborted.load(Ordering::SeqCst) || cancelled() {
+                    break;
+                }
+
+                let install_dir = install_dir(&batch, job, jobs);
+                if let Err(reason) = download_batch(job, batch, install_dir.as_ref(), &options, &sender) {
+                    aborted.store(true, Ordering::SeqCst);
+                    let _ = sender.send(Event::Aborted { reason });
+                    break;
+                }
+            }
+        });
+    }
+
+    Download { events: receiver }
+}
+
+/// The batches `items` are downloaded in, and how many jobs download them.
+/// Items with different install dirs never share a batch.
+pub fn plan(items: &[DownloadItem], options: &Options) -> (Vec<Vec<DownloadItem>>, usize) {
+    // smaller batches when there are fewer batches than jobs, so every job gets some
+    let jobs = options.jobs.max(1);
+    let batch_size = match jobs {
+        1 => options.batch_size,
+        _ => options.batch_size.min(items.len().div_ceil(jobs)).max(1)
+    };
+
+    let mut groups: Vec<Vec<DownloadItem>> = vec![];
+    for item in items.iter() {
+        match groups.iter_mut().find(|x| x[0].install_dir == item.install_dir) {
+            Some(group) => group.push(item.clone()),
+            None => groups.push(vec![item.clone()])
+        }
+    }
+
+    let batches: Vec<Vec<DownloadItem>> = groups.iter()
+        .flat_map(|x| x.chunks(batch_size).map(|x| x.to_vec()))
+        .collect();
+    let jobs = jobs.min(batches.len());
+
+    (batches, jobs)
+}
+
+/// The install dir `job` downloads `batch` into: the batch's own, otherwise
+/// a dir per job when more than one is running so the processes don't fight over locks
+pub fn install_dir(batch: &[DownloadItem], job: usize, jobs: usize) -> Option<PathBuf> {
+    match (batch.first().and_then(|x| x.install_dir.clone()), jobs) {
+        (Some(dir), _) => Some(dir),
+        (None, 1) => None,
+        (None, _) => Some(job_dir(job))
+    }
+}
+
+/// The install dir of `job` when more than one job is running
+pub fn job_dir(job: usize) -> PathBuf {
+    let dir = PathBuf::from(JOBS_DIR).join(format!("job-{}", job));
+
+    // steamcmd resolves relative paths against its own dir
+    std::env::current_dir().map_or(dir.clone(), |cwd| cwd.join(dir))
+}
+
+/// Downloads a batch, retrying the items that failed. Only fails if the downloader can't be started.
+fn download_batch(
+    job: usize, batch: Vec<DownloadItem>, install_dir: Option<&PathBuf>, options: &Options, sender: &Sender<Event>
+) -> Result<(), String> {
+    let mut pending = batch;
+
+    for attempt in 1..=options.retries + 1 {
+        if attempt > 1 {
+            // 10s, 20s, 40s, ... with the default delay
+            let delay = options.retry_delay.saturating_mul(1 << (attempt - 2).min(16));
+
+            let _ = sender.send(Event::Retrying { job, items: pending.len(), delay, attempt });
+
+            let until = Instant::now() + Duration::from_secs(delay);
+            while Instant::now() < until && !cancelled() {
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            if cancelled() {
+                for item in pending {
+                    let _ = sender.send(Event::Finished { job, item, result: ItemResult::Cancelled, attempt, last: true });
+                }
+                break;
+            }
+        }
+
+        let _ = sender.send(Event::Started { job, items: pending.len(), attempt });
+        let context = Job {
+            id: job, install_dir, passwords: &options.passwords, watchdog: options.watchdog, sender
+        };
+        let results = options.downloader.download(&context, &pending)
+            .map_err(|err| format!("couldn't start {}: {}", options.downloader.name(), err.kind()))?;
+
+        let mut failed: Vec<DownloadItem> = vec![];
+        for (item, result) in pending.into_iter().zip(results) {
+            let last = attempt > options.retries || !matches!(result, ItemResult::Failed { .. });
+            if !last {
+                failed.push(item.clone());
+            }
+
+            let _ = sender.send(Event::Finished { job, item, result, attempt, last });
+        }
+
+        pending = failed;
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The job a downloader runs in, passed to `Downloader::download`
+pub struct Job<'a> {
+    pub id: usize,
+    /// Where the job downloads to, when it isn't the tool's default
+    pub install_dir: Option<&'a PathBuf>,
+    /// Passwords of the accounts the items log in with, by username
+    pub passwords: &'a HashMap<String, String>,
+    /// Seconds without output after which the process is killed, 0 to wait forever
+    pub watchdog: u64,
+    pub sender: &'a Sender<Event>,
+}
+
+impl<'a> Job<'a> {
+    /// Forwards a line of output, for tools that don't run a process
+    pub fn output(&self, line: String) {
+        let _ = self.sender.send(Event::Output { job: self.id, line });
+    }
+}
+
+/// How a process started with `run_process` ended
+pub struct ProcessEnd {
+    pub status: ExitStatus,
+    /// Killed by the watchdog
+    pub timed_out: bool,
+}
+
+impl ProcessEnd {
+    /// The result of an item the tool didn't report on, e.g. because it exited early
+    pub fn missing_result(&self, job: &Job) -> ItemResult {
+        match (cancelled(), self.timed_out, self.status.success()) {
+            (true, _, _) => ItemResult::Cancelled,
+            (_, true, _) => ItemResult::Failed { reason: format!("no output for {}s", job.watchdog) },
+            (_, _, true) => ItemResult::Failed { reason: String::from("no result reported") },
+            (_, _, false) => ItemResult::Failed { reason: format!("exited with {}", self.status) }
+        }
+    }
+}
+
+/// Runs `command`, calling `on_line` with every line of its stdout and
+/// forwarding its output as it arrives. Prompts are relayed to the user.
+/// The process is killed when the download is cancelled or when it has
+/// been quiet for `job.watchdog` seconds, together with everything it started.
+pub fn run_process(job: &Job, mut command: Command, mut on_line: impl FnMut(&str)) -> std::io::Result<ProcessEnd> {
+    let (id, watchdog, sender) = (job.id, job.watchdog, job.sender);
+
+    // its own process group, so the steamcmd that `steamcmd.sh` starts can be killed with it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let child = Arc::new(Mutex::new(child));
+    let last_output = Arc::new(Mutex::new(Instant::now()));
+    let exited = Arc::new(AtomicBool::new(false));
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let killed = Arc::new(AtomicBool::new(false));
+    // the process is quiet while it waits for an answer, that doesn't count for the watchdog
+    let prompting = Arc::new(AtomicBool::new(false));
+
+    let stderr_thread = {
+        let (sender, last_output) = (sender.clone(), last_output.clone());
+
+        thread::spawn(move || {
+            for_each_line(stderr, |line| {
+                *last_output.lock().unwrap() = Instant::now();
+                let _ = sender.send(Event::Output { job: id, line });
+            });
+        })
+    };
+
+    let watchdog_thread = {
+        let (child, last_output, exited, timed_out, killed, prompting) =
+            (child.clone(), last_output.clone(), exited.clone(), timed_out.clone(), killed.clone(), prompting.clone());
+
+        thread::spawn(move || {
+            while !exited.load(Ordering::SeqCst) {
+                thread::sleep(POLL_INTERVAL);
+
+                let quiet = last_output.lock().unwrap().elapsed();
+                if watchdog > 0 && quiet >= Duration::from_secs(watchdog) && !prompting.load(Ordering::SeqCst) {
+                    timed_out.store(true, Ordering::SeqCst);
+                }
+
+                if cancelled() || timed_out.load(Ordering::SeqCst) {
+                    kill(&mut child.lock().unwrap());
+                    killed.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+        })
+    };
+
+    // read on a thread of its own, a process that escaped the kill may keep the pipe open
+    let (lines, stdout_lines) = mpsc::channel();
+    thread::spawn(move || for_each_line(stdout, |line| {
+        let _ = lines.send(line);
+    }));
+
+    loop {
+        let line = match stdout_lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => line,
+            // what was read before the kill is still handled, nothing after it
+            Err(RecvTimeoutError::Timeout) if killed.load(Ordering::SeqCst) => break,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break
+        };
+
+        *last_output.lock().unwrap() = Instant::now();
+        on_line(&line);
+
+        if steamcmd::is_prompt(&line) {
+            let (reply, answer) = mpsc::channel();
+
+            prompting.store(true, Ordering::SeqCst);
+            let _ = sender.send(Event::Prompt { job: id, text: line, reply });
+            if let Ok(answer) = answer.recv() {
+                let _ = writeln!(stdin, "{}", answer).and_then(|_| stdin.flush());
+            }
+
+            *last_output.lock().unwrap() = Instant::now();
+            prompting.store(false, Ordering::SeqCst);
+            continue;
+        }
+
+        let _ = sender.send(Event::Output { job: id, line });
+    }
+
+    // polled so the watchdog can still get at the child to kill it
+    let status = loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            break status;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+    exited.store(true, Ordering::SeqCst);
+    let _ = watchdog_thread.join();
+    if !killed.load(Ordering::SeqCst) {
+        let _ = stderr_thread.join();
+    }
+
+    Ok(ProcessEnd { status, timed_out: timed_out.load(Ordering::SeqCst) })
+}
+
+/// Kills `child` and the processes it started, which share its process group on unix
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: only sends a signal, to the group `run_process` created for the child
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+
+    let _ = child.kill();
+}
+
+/// Calls `f` with every line of `reader` until it is closed. steamcmd redraws
+/// its progress with `\r`, so that ends a line too. Prompts are passed on as
+/// soon as they arrive since steamcmd waits for an answer without ending the line.
+fn for_each_line(mut reader: impl Read, mut f: impl FnMut(String)) {
+    let mut chunk = [0u8; 4096];
+    let mut pending: Vec<u8> = vec![];
+
+    loop {
+        let len = match reader.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => len
+        };
+        pending.extend_from_slice(&chunk[..len]);
+
+        while let Some(end) = pending.iter().position(|x| *x == b'\n' || *x == b'\r') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]).to_string();
+
+            if !line.trim().is_empty() {
+                f(line);
+            }
+        }
+
+        if !pending.is_empty() && steamcmd::is_prompt(&String::from_utf8_lossy(&pending)) {
+            f(String::from_utf8_lossy(&pending).to_string());
+            pending.clear();
+        }
+    }
+
+    if !String::from_utf8_lossy(&pending).trim().is_empty() {
+        f(String::from_utf8_lossy(&pending).to_string());
+    }
+}
+
+/// The status line redrawn at the bottom of the output while downloading, e.g.
+/// `[12/120] job 1: Hospital [2009463077] 1.2 MiB / 4.5 MiB`. Only drawn
+/// when stdout is a terminal.
+pub struct ProgressLine {
+    total: usize,
+    done: usize,
+    /// What each job is downloading
+    current: Vec<Option<JobProgress>>,
+    visible: bool,
+    enabled: bool,
+}
+
+/// The item a job is downloading, and its bytes if steamcmd reported them
+#[derive(Clone)]
+struct JobProgress {
+    label: String,
+    bytes: Option<(u64, u64)>,
+}
+
+impl ProgressLine {
+    pub fn new(total: usize, jobs: usize) -> Self {
+        ProgressLine {
+            total,
+            done: 0,
+            current: vec![None; jobs.max(1)],
+            visible: false,
+            enabled: std::io::stdout().is_terminal()
+        }
+    }
+
+    pub fn set_item(&mut self, job: usize, label: String) {
+        if let Some(current) = self.current.get_mut(job - 1) {
+            *current = Some(JobProgress { label, bytes: None });
+        }
+    }
+
+    pub fn set_bytes(&mut self, job: usize, done: u64, total: u64) {
+        if let Some(Some(current)) = self.current.get_mut(job - 1) {
+            current.bytes = Some((done, total));
+        }
+    }
+
+    pub fn finish_item(&mut self, job: usize) {
+        self.done += 1;
+
+        if let Some(current) = self.current.get_mut(job - 1) {
+            *current = None;
+        }
+    }
+
+    /// Removes the line so a log message can be printed in its place
+    pub fn clear(&mut self) {
+        if self.visible {
+            print!("\r\x1b[2K");
+            let _ = std::io::stdout().flush();
+            self.visible = false;
+        }
+    }
+
+    pub fn draw(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        let jobs = self.current.len();
+        let mut parts: Vec<String> = vec![];
+        for (i, current) in self.current.iter().enumerate() {
+            let current = match current {
+                Some(current) => current,
+                None => continue
+            };
+
+            let mut part = match jobs {
+                1 => current.label.clone(),
+                _ => format!("job {}: {}", i + 1, current.label)
+            };
+            if let Some((done, total)) = current.bytes {
+                part += &format!(" {} / {}", format_bytes(done), format_bytes(total));
+            }
+
+            parts.push(part);
+        }
+
+        print!("\r\x1b[2K[{}/{}] {}", self.done, self.total, parts.join(" | "));
+        let _ = std::io::stdout().flush();
+        self.visible = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::{MockDownloader, MockScript};
+    use crate::queue::{ItemId, Queue, QueueEntry, Source, Status, StatusFilter};
+
+    /// `cancel` is global, so downloads in tests can't run at the same time
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn queue(ids: &[u64]) -> Queue {
+        let mut queue = Queue::default();
+        for id in ids {
+            queue.add(QueueEntry::new(
+                String::from("294100"), String::from("rimworld"), ItemId(*id), None, Source::Manual
+            ));
+        }
+
+        queue
+    }
+
+    fn options(script: &str, retries: u32) -> Options {
+        let script: MockScript = serde_json::from_str(script).unwrap();
+
+        Options {
+            downloader: Arc::new(MockDownloader::new(script)),
+            batch_size: 50,
+            retries,
+            retry_delay: 0,
+            jobs: 1,
+            watchdog: 0,
+            passwords: HashMap::new(),
+        }
+    }
+
+    /// Downloads the pending items of `queue` and sets their statuses the way
+    /// `Manager::download` does. Returns the attempts each item took and how
+    /// often the download retried.
+    fn run(queue: &mut Queue, options: Options, cancel_on: Option<ItemId>) -> (HashMap<ItemId, u32>, usize) {
+        let items: Vec<DownloadItem> = queue.select(&"pending".parse().unwrap())
+            .into_iter()
+            .map(DownloadItem::from)
+            .collect();
+
+        let mut attempts: HashMap<ItemId, u32> = HashMap::new();
+        let mut retries = 0;
+
+        for event in start(items, options) {
+            match event {
+                Event::Output { line, .. } if cancel_on.is_some_and(|id| line.contains(&format!("item {} ", id))) => {
+                    cancel();
+                },
+                Event::Retrying { .. } => retries += 1,
+                Event::Finished { item, result, attempt, last: true, .. } => {
+                    let status = match result {
+                        ItemResult::Downloaded { .. } => Status::Downloaded,
+                        ItemResult::Failed { reason } => Status::Failed { reason },
+                        ItemResult::Cancelled => Status::Cancelled
+                    };
+                    queue.set_status(&item.app_id, item.item_id, status);
+                    attempts.insert(item.item_id, attempt);
+                },
+                _ => ()
+            }
+        }
+
+        (attempts, retries)
+    }
+
+    /// Like `steamcmd.sh`, which starts the real steamcmd without `exec`
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_kills_what_a_wrapper_started() {
+        let _lock = LOCK.lock().unwrap_or_else(|x| x.into_inner());
+        CANCELLED.store(false, Ordering::SeqCst);
+
+        let (sender, _events) = mpsc::channel();
+        let passwords = HashMap::new();
+        let job = Job { id: 1, install_dir: None, passwords: &passwords, watchdog: 1, sender: &sender };
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo starting; sleep 30 & echo $!; wait; echo done"]);
+
+        let started = Instant::now();
+        let mut lines: Vec<String> = vec![];
+        let end = run_process(&job, command, |line| lines.push(line.to_string())).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+        assert!(end.timed_out);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "starting");
+
+        // the sleep the wrapper started is gone too, or a zombie nobody reaped yet
+        #[cfg(target_os = "linux")]
+        {
+            let stat = format!("/proc/{}/stat", lines[1].trim());
+            let gone = || std::fs::read_to_string(&stat).map_or(true, |x| x.split_whitespace().nth(2) == Some("Z"));
+            let until = Instant::now() + Duration::from_secs(2);
+            while !gone() && Instant::now() < until {
+                thread::sleep(POLL_INTERVAL);
+            }
+            assert!(gone(), "{} is still running", lines[1]);
+        }
+    }
+
+    fn status(queue: &Queue, id: u64) -> &Status {
+        &queue.entries.iter().find(|x| x.item_id == ItemId(id)).unwrap().status
+    }
+
+    #[test]
+    fn retries_failed_items() {
+        let _lock = LOCK.lock().unwrap_or_else(|x| x.into_inner());
+        let mut queue = queue(&[111, 222, 333]);
+        let options = options(r#"{ "items": { "111": ["fail:Timeout", "ok"], "222": ["fail:Access Denied"] } }"#, 2);
+
+        let (attempts, retries) = run(&mut queue, options, None);
+
+        assert_eq!(attempts[&ItemId(111)], 2);
+        assert_eq!(attempts[&ItemId(222)], 3);
+        assert_eq!(attempts[&ItemId(333)], 1);
+        assert_eq!(retries, 2);
+
+        assert_eq!(*status(&queue, 111), Status::Downloaded);
+        assert_eq!(*status(&queue, 222), Status::Failed { reason: String::from("Access Denied") });
+        assert_eq!(*status(&queue, 333), Status::Downloaded);
+
+        // the next download picks the failed item up again
+        let filter: StatusFilter = "pending,failed,cancelled".parse().unwrap();
+        let remaining: Vec<ItemId> = queue.select(&filter).iter().map(|x| x.item_id).collect();
+        assert_eq!(remaining, vec![ItemId(222)]);
+    }
+
+    #[test]
+    fn cancelled_items_stay_queued() {
+        let _lock = LOCK.lock().unwrap_or_else(|x| x.into_inner());
+        let mut queue = queue(&[111, 222, 333]);
+        let options = options(r#"{ "items": { "222": ["hang"] } }"#, 2);
+
+        let (attempts, retries) = run(&mut queue, options, Some(ItemId(222)));
+
+        assert_eq!(attempts[&ItemId(222)], 1);
+        assert_eq!(retries, 0);
+
+        assert_eq!(*status(&queue, 111), Status::Downloaded);
+        assert_eq!(*status(&queue, 222), Status::Cancelled);
+        assert_eq!(*status(&queue, 333), Status::Cancelled);
+
+        let filter: StatusFilter = "pending,failed,cancelled".parse().unwrap();
+        assert_eq!(queue.select(&filter).len(), 2);
+    }
+}
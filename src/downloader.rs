@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::download::{self, Job};
+use crate::queue::ItemId;
+use crate::steamcmd::{self, DownloadItem, ItemResult};
+use crate::utils;
+
+/// A tool that downloads workshop items
+pub trait Downloader: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Downloads `items` in one go, forwarding output through `job` as it
+    /// arrives. Returns a result for every item, in the same order. Only
+    /// fails if the tool can't be started at all.
+    fn download(&self, job: &Job, items: &[DownloadItem]) -> std::io::Result<Vec<ItemResult>>;
+
+    /// The processes `download` starts for `items`, in order
+    fn commands(
+        &self, install_dir: Option<&PathBuf>, items: &[DownloadItem], passwords: &HashMap<String, String>
+    ) -> Vec<Command>;
+
+    /// Where the items of `app_id` end up, inside `install_dir` when the job has one
+    fn content_dir(&self, install_dir: Option<&PathBuf>, app_id: &str) -> PathBuf;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloaderKind {
+    Steamcmd,
+    DepotDownloader,
+    Mock,
+}
+
+impl DownloaderKind {
+    pub const NAMES: [&'static str; 3] = ["steamcmd", "depotdownloader", "mock"];
+}
+
+impl FromStr for DownloaderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "steamcmd" => Ok(DownloaderKind::Steamcmd),
+            "depotdownloader" => Ok(DownloaderKind::DepotDownloader),
+            "mock" => Ok(DownloaderKind::Mock),
+            _ => Err(format!(
+                "'{}' is not a valid downloader, expected one of: {}",
+                s, DownloaderKind::NAMES.join(", ")
+            ))
+        }
+    }
+}
+
+// ==========================
+// steamcmd
+// ==========================
+pub struct SteamcmdDownloader {
+    pub program: PathBuf
+}
+
+impl Downloader for SteamcmdDownloader {
+    fn name(&self) -> &'static str {
+        "steamcmd"
+    }
+
+    fn download(&self, job: &Job, items: &[DownloadItem]) -> std::io::Result<Vec<ItemResult>> {
+        if let Some(dir) = job.install_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let command = self.commands(job.install_dir, items, job.passwords).remove(0);
+        let mut results: Vec<(ItemId, ItemResult)> = vec![];
+        let end = download::run_process(job, command, |line| {
+            if let Some(result) = steamcmd::parse_line(line) {
+                results.push(result);
+            }
+        })?;
+
+        let missing = end.missing_result(job);
+        Ok(items.iter().map(|item| {
+            // the last result wins if steamcmd retried an item on its own
+            results.iter().rev()
+                .find(|(id, _)| *id == item.item_id)
+                .map(|(_, result)| result.clone())
+                .unwrap_or(missing.clone())
+        }).collect())
+    }
+
+    fn commands(
+        &self, install_dir: Option<&PathBuf>, items: &[DownloadItem], passwords: &HashMap<String, String>
+    ) -> Vec<Command> {
+        let mut command = Command::new(&self.program);
+
+        if let Some(dir) = install_dir {
+            command.arg("+force_install_dir").arg(dir);
+        }
+        command.args(steamcmd::download_args(items, passwords));
+
+        vec![command]
+    }
+
+    fn content_dir(&self, install_dir: Option<&PathBuf>, app_id: &str) -> PathBuf {
+        // steamcmd installs next to itself by default
+        let base = match install_dir {
+            Some(dir) => dir.clone(),
+            None => self.program.parent().map(|x| x.to_path_buf()).unwrap_or_default()
+        };
+
+        base.join("steamapps").join("workshop").join("content").join(app_id)
+    }
+}
+
+// ==========================
+// DepotDownloader
+// ==========================
+
+/// Overrides the DepotDownloader path from the config
+pub const DEPOTDOWNLOADER_ENV_VAR: &str = "RSW_DEPOTDOWNLOADER";
+
+const DEPOTDOWNLOADER_NAMES: [&str; 3] = ["DepotDownloader", "depotdownloader", "DepotDownloader.exe"];
+
+/// Where items go when the job has no install dir of its own, as `<dir>/<app id>/<item id>`
+const DEPOTDOWNLOADER_DIR: &str = "workshop";
+
+/// [DepotDownloader](https://github.com/SteamRE/DepotDownloader), which
+/// downloads a single item per run
+pub struct DepotDownloader {
+    pub program: PathBuf
+}
+
+impl DepotDownloader {
+    /// Same order as `steamcmd::locate`: `RSW_DEPOTDOWNLOADER`, `configured`, then `PATH`
+    pub fn locate(configured: Option<&str>) -> Result<PathBuf, String> {
+        let env_value = std::env::var(DEPOTDOWNLOADER_ENV_VAR).ok().filter(|x| !x.trim().is_empty());
+        let given = [
+            (env_value, DEPOTDOWNLOADER_ENV_VAR),
+            (configured.map(|x| x.to_string()), "'depotdownloader' in config.json"),
+        ];
+
+        for (path, origin) in given {
+            if let Some(path) = path {
+                return match Path::new(&path).is_file() {
+                    true => Ok(PathBuf::from(path)),
+                    false => Err(format!("DepotDownloader not found at '{}' (from {})", path, origin))
+                };
+            }
+        }
+
+        utils::find_on_path(&DEPOTDOWNLOADER_NAMES).ok_or_else(|| format!(
+            "DepotDownloader not found on PATH, set 'depotdownloader' in config.json or {}",
+            DEPOTDOWNLOADER_ENV_VAR
+        ))
+    }
+
+    /// `Total downloaded: 1234 bytes (5678 bytes uncompressed) from 1 depots`
+    fn parse_total(line: &str) -> Option<u64> {
+        let rest = line.trim().strip_prefix("Total downloaded: ")?;
+
+        rest.split_whitespace().next()?.parse().ok()
+    }
+}
+
+impl Downloader for DepotDownloader {
+    fn name(&self) -> &'static str {
+        "DepotDownloader"
+    }
+
+    fn download(&self, job: &Job, items: &[DownloadItem]) -> std::io::Result<Vec<ItemResult>> {
+        let commands = self.commands(job.install_dir, items, job.passwords);
+        let mut results: Vec<ItemResult> = vec![];
+
+        for (item, command) in items.iter().zip(commands) {
+            if download::cancelled() {
+                results.push(ItemResult::Cancelled);
+                continue;
+            }
+
+            job.output(format!("Downloading item {} ...", item.item_id));
+
+            let mut total: Option<u64> = None;
+            let mut last_error: Option<String> = None;
+            let end = download::run_process(job, command, |line| {
+                if let Some(bytes) = DepotDownloader::parse_total(line) {
+                    total = Some(bytes);
+                }
+
+                if line.contains("Error") || line.contains("error") {
+                    last_error = Some(line.trim().to_string());
+                }
+            })?;
+
+            let dir = self.content_dir(job.install_dir, &item.app_id).join(item.item_id.to_string());
+            results.push(match (end.status.success(), total) {
+                (true, Some(bytes)) => ItemResult::Downloaded { path: dir.display().to_string(), bytes: Some(bytes) },
+                _ => match end.missing_result(job) {
+                    ItemResult::Failed { reason } => ItemResult::Failed { reason: last_error.unwrap_or(reason) },
+                    result => result
+                }
+            });
+        }
+
+        Ok(results)
+    }
+
+    fn commands(
+        &self, install_dir: Option<&PathBuf>, items: &[DownloadItem], passwords: &HashMap<String, String>
+    ) -> Vec<Command> {
+        items.iter().map(|item| {
+            let dir = self.content_dir(install_dir, &item.app_id).join(item.item_id.to_string());
+            let mut command = Command::new(&self.program);
+            command.arg("-app").arg(&item.app_id)
+                .arg("-pubfile").arg(item.item_id.to_string())
+                .arg("-dir").arg(&dir);
+
+            if let Some(username) = &item.account {
+                command.arg("-username").arg(username);
+
+                if let Some(password) = passwords.get(username).filter(|x| !x.is_empty()) {
+                    command.arg("-password").arg(password);
+                }
+                command.arg("-remember-password");
+            }
+
+            command
+        }).collect()
+    }
+
+    fn content_dir(&self, install_dir: Option<&PathBuf>, app_id: &str) -> PathBuf {
+        match install_dir {
+            Some(dir) => dir.join(app_id),
+            None => PathBuf::from(DEPOTDOWNLOADER_DIR).join(app_id)
+        }
+    }
+}
+
+// ==========================
+// Mock
+// ==========================
+
+/// Points the mock downloader at a script, overrides `mock_script` in the config
+pub const MOCK_SCRIPT_ENV_VAR: &str = "RSW_MOCK_SCRIPT";
+
+/// What the mock downloader does, read from a json file like
+///
+/// ```json
+/// { "delay_ms": 200, "items": { "123": ["fail:Timeout", "ok"], "*": ["ok"] } }
+/// ```
+///
+/// Each item goes through its list one attempt at a time and stays on the
+/// last entry, `*` is used for items that aren't listed. `ok` downloads the
+/// item, `fail:<reason>` fails it and `hang` waits until the watchdog or a
+/// cancel stops it. Without a script every item downloads.
+#[derive(Debug, Default, Deserialize)]
+pub struct MockScript {
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default)]
+    pub items: HashMap<String, Vec<String>>,
+}
+
+/// Downloads nothing, reports what its script says. Its output looks like
+/// steamcmd's, so progress and log files work the same.
+pub struct MockDownloader {
+    script: MockScript,
+    /// Attempts so far per item
+    attempts: Mutex<HashMap<ItemId, usize>>,
+}
+
+impl MockDownloader {
+    pub fn new(script: MockScript) -> Self {
+        MockDownloader { script, attempts: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|_| format!("Couldn't read the mock script '{}'", path))?;
+        let script = serde_json::from_str::<MockScript>(&contents)
+            .map_err(|err| format!("Invalid mock script '{}': {}", path, err))?;
+
+        Ok(MockDownloader::new(script))
+    }
+
+    fn next_step(&self, item_id: ItemId) -> String {
+        let mut attempts = self.attempts.lock().unwrap();
+        let attempt = attempts.entry(item_id).or_insert(0);
+        *attempt += 1;
+
+        let steps = self.script.items.get(&item_id.to_string())
+            .or_else(|| self.script.items.get("*"));
+
+        match steps {
+            Some(steps) if !steps.is_empty() => steps[(*attempt - 1).min(steps.len() - 1)].clone(),
+            _ => String::from("ok")
+        }
+    }
+}
+
+impl Downloader for MockDownloader {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn download(&self, job: &Job, items: &[DownloadItem]) -> std::io::Result<Vec<ItemResult>> {
+        let mut results: Vec<ItemResult> = vec![];
+
+        for item in items.iter() {
+            if download::cancelled() {
+                results.push(ItemResult::Cancelled);
+                continue;
+            }
+
+            job.output(format!("Downloading item {} ...", item.item_id));
+            thread::sleep(Duration::from_millis(self.script.delay_ms));
+
+            let step = self.next_step(item.item_id);
+            let result = match step.as_str() {
+                "ok" => {
+                    let path = self.content_dir(job.install_dir, &item.app_id).join(item.item_id.to_string());
+                    job.output(format!("Success. Downloaded item {} to \"{}\" (0 bytes)", item.item_id, path.display()));
+
+                    ItemResult::Downloaded { path: path.display().to_string(), bytes: Some(0) }
+                },
+                "hang" => {
+                    // like a steamcmd that stopped responding
+                    let quiet = Duration::from_secs(job.watchdog);
+                    let started = std::time::Instant::now();
+                    while !download::cancelled() && (job.watchdog == 0 || started.elapsed() < quiet) {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+
+                    match download::cancelled() {
+                        true => ItemResult::Cancelled,
+                        false => ItemResult::Failed { reason: format!("no output for {}s", job.watchdog) }
+                    }
+                },
+                step => {
+                    let reason = step.strip_prefix("fail:").unwrap_or(step).to_string();
+                    job.output(format!("ERROR! Download item {} failed ({}).", item.item_id, reason));
+
+                    ItemResult::Failed { reason }
+                }
+            };
+
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Nothing is started, the script is followed in-process
+    fn commands(&self, _: Option<&PathBuf>, _: &[DownloadItem], _: &HashMap<String, String>) -> Vec<Command> {
+        vec![]
+    }
+
+    fn content_dir(&self, install_dir: Option<&PathBuf>, app_id: &str) -> PathBuf {
+        install_dir.cloned().unwrap_or_else(|| PathBuf::from("mock")).join(app_id)
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::queue::{ItemId, QueueEntry, StatusFilter};
+use crate::steamcmd::{self, DownloadItem};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A single line of `+login anonymous +workshop_download_item ... +quit` args
+    SteamcmdArgs,
+    /// One command per line, for `steamcmd +runscript <file>`
+    Runscript,
+    Json,
+    Csv,
+    /// Comma separated item ids, the format `convert` reads
+    Ids,
+}
+
+impl ExportFormat {
+    pub const NAMES: [&'static str; 5] = ["steamcmd-args", "runscript", "json", "csv", "ids"];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::SteamcmdArgs => "txt",
+            ExportFormat::Runscript => "runscript.txt",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ids => "ids.txt",
+        }
+    }
+
+    /// `<app names joined by +>.<extension>`, with a random suffix before
+    /// the extension when `random_name` is set
+    pub fn default_path(&self, entries: &[&QueueEntry], random_name: bool) -> String {
+        let mut app_names: Vec<&str> = vec![];
+        for entry in entries.iter() {
+            if !app_names.contains(&entry.app_name.as_str()) {
+                app_names.push(&entry.app_name);
+            }
+        }
+
+        match random_name {
+            true => format!(
+                "{}-{}.{}",
+                app_names.join("+"),
+                Alphanumeric.sample_string(&mut rand::thread_rng(), 8),
+                self.extension()
+            ),
+            false => format!("{}.{}", app_names.join("+"), self.extension())
+        }
+    }
+
+    /// `accounts` maps app ids to the username they are downloaded with, apps
+    /// that aren't in it log in anonymously. Passwords are never exported.
+    /// `install_dirs` maps app ids to their `force_install_dir`.
+    pub fn render(
+        &self, entries: &[&QueueEntry], accounts: &HashMap<String, String>, install_dirs: &HashMap<String, PathBuf>
+    ) -> String {
+        match self {
+            ExportFormat::SteamcmdArgs => steamcmd_args(&download_items(entries, accounts, install_dirs)).join(" "),
+            ExportFormat::Runscript => runscript(&download_items(entries, accounts, install_dirs)),
+            ExportFormat::Json => json(entries),
+            ExportFormat::Csv => csv(entries),
+            ExportFormat::Ids => entries.iter()
+                .map(|x| x.item_id.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+}
+
+/// What `Manager::export` writes and where
+pub struct ExportOptions<'a> {
+    pub filter: StatusFilter,
+    pub format: ExportFormat,
+    /// Named after the apps when not set
+    pub out: Option<&'a String>,
+    pub random_name: bool,
+    /// Logs every item in with this account instead of the account of its alias
+    pub account: Option<&'a String>,
+    /// Installs every item into this dir instead of the dir of its alias
+    pub install_dir: Option<&'a String>,
+    pub dry_run: bool,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "steamcmd-args" => Ok(ExportFormat::SteamcmdArgs),
+            "runscript" => Ok(ExportFormat::Runscript),
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "ids" => Ok(ExportFormat::Ids),
+            _ => Err(format!(
+                "'{}' is not a valid format, expected one of: {}",
+                s, ExportFormat::NAMES.join(", ")
+            ))
+        }
+    }
+}
+
+/// Items with an install dir go after the ones without, grouped by dir, as
+/// steamcmd can't go back to its own dir once it was changed
+fn download_items(
+    entries: &[&QueueEntry], accounts: &HashMap<String, String>, install_dirs: &HashMap<String, PathBuf>
+) -> Vec<DownloadItem> {
+    let mut items: Vec<DownloadItem> = entries.iter()
+        .map(|x| DownloadItem {
+            account: accounts.get(&x.app_id).cloned(),
+            install_dir: install_dirs.get(&x.app_id).cloned(),
+            ..DownloadItem::from(*x)
+        })
+        .collect();
+
+    items.sort_by(|a, b| a.install_dir.cmp(&b.install_dir));
+    items
+}
+
+/// The args steamcmd is started with to download `items`, changing the
+/// install dir (and logging in again) before every group that has one
+fn steamcmd_args(items: &[DownloadItem]) -> Vec<String> {
+    let mut args: Vec<String> = vec![];
+
+    for group in items.chunk_by(|a, b| a.install_dir == b.install_dir) {
+        if let Some(dir) = &group[0].install_dir {
+            args.push(String::from("+force_install_dir"));
+            args.push(dir.display().to_string());
+        }
+
+        let mut group_args = steamcmd::download_args(group, &HashMap::new());
+        group_args.pop(); // +quit, added once at the end
+        args.extend(group_args);
+    }
+
+    match args.is_empty() {
+        true => steamcmd::download_args(&[], &HashMap::new()),
+        false => {
+            args.push(String::from("+quit"));
+            args
+        }
+    }
+}
+
+fn runscript(items: &[DownloadItem]) -> String {
+    // accounts without a password need steamcmd to ask for it
+    let prompt = match items.iter().any(|x| x.account.is_some()) {
+        true => "@NoPromptForPassword 0",
+        false => "@NoPromptForPassword 1"
+    };
+    let mut lines: Vec<String> = vec![String::from("@ShutdownOnFailedCommand 0"), String::from(prompt)];
+
+    for group in items.chunk_by(|a, b| a.install_dir == b.install_dir) {
+        if let Some(dir) = &group[0].install_dir {
+            lines.push(format!("force_install_dir \"{}\"", dir.display()));
+        }
+
+        // steamcmd wants the install dir before logging in, so every group logs in again
+        let mut current: Option<Option<&str>> = None;
+        for item in group.iter() {
+            if current != Some(item.account.as_deref()) {
+                lines.push(steamcmd::login_command(item.account.as_deref(), &HashMap::new()).join(" "));
+                current = Some(item.account.as_deref());
+            }
+
+            lines.push(format!("workshop_download_item {} {}", item.app_id, item.item_id));
+        }
+    }
+
+    lines.push(String::from("quit"));
+    lines.join("\n") + "\n"
+}
+
+fn json(entries: &[&QueueEntry]) -> String {
+    let mut apps: Vec<serde_json::Value> = vec![];
+    let mut current_app = "";
+
+    for entry in entries.iter() {
+        if entry.app_id != current_app {
+            apps.push(serde_json::json!({
+                "app_id": entry.app_id,
+                "app_name": entry.app_name,
+                "items": []
+            }));
+            current_app = &entry.app_id;
+        }
+
+        apps.last_mut().unwrap()["items"].as_array_mut().unwrap().push(serde_json::json!({
+            "id": entry.item_id,
+            "title": entry.title,
+            "status": entry.status.name()
+        }));
+    }
+
+    serde_json::to_string_pretty(&serde_json::json!({ "apps": apps })).unwrap()
+}
+
+fn csv(entries: &[&QueueEntry]) -> String {
+    let mut lines: Vec<String> = vec![String::from("app_id,app_name,item_id,title,status")];
+
+    for entry in entries.iter() {
+        lines.push(format!(
+            "{},{},{},{},{}",
+            entry.app_id,
+            csv_field(&entry.app_name),
+            entry.item_id,
+            csv_field(entry.title.as_deref().unwrap_or("")),
+            entry.status.name()
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+
+    value.to_string()
+}
+
+/// Reads the items back out of an export, with the `force_install_dir` and
+/// `login` they come after. Passwords are never exported, so only the username is kept.
+///
+/// Steamcmd args are split into commands at every `+`, runscripts have one
+/// command per line, so both are read the same way. JSON manifests keep their titles.
+pub fn parse_export(contents: &str) -> Result<Vec<DownloadItem>, String> {
+    if contents.trim_start().starts_with('{') {
+        return parse_json(contents);
+    }
+
+    let mut items = vec![];
+    let mut account: Option<String> = None;
+    let mut install_dir: Option<PathBuf> = None;
+
+    for (command, args) in commands(contents) {
+        match command.as_str() {
+            "workshop_download_item" => match args.as_slice() {
+                [app_id, id, ..] => match (app_id.chars().all(|c| c.is_ascii_digit()), id.parse::<ItemId>()) {
+                    (true, Ok(item_id)) => items.push(DownloadItem {
+                        app_id: app_id.clone(), item_id, title: None, account: account.clone(), install_dir: install_dir.clone()
+                    }),
+                    _ => return Err(format!("Invalid item '{} {}'", app_id, id))
+                },
+                _ => return Err(String::from("Missing app id or item id after workshop_download_item"))
+            },
+            "login" => account = args.first().filter(|x| *x != "anonymous").cloned(),
+            // unquoted in steamcmd args, so a dir with spaces is split over several args
+            "force_install_dir" => install_dir = Some(args.join(" ").trim_matches('"').to_string())
+                .filter(|x| !x.is_empty())
+                .map(PathBuf::from),
+            _ => ()
+        }
+    }
+
+    Ok(items)
+}
+
+/// The commands of a steamcmd export and their args, without the leading `+`
+fn commands(contents: &str) -> Vec<(String, Vec<String>)> {
+    let mut commands: Vec<(String, Vec<String>)> = vec![];
+
+    match contents.split_whitespace().any(|x| x.starts_with('+')) {
+        true => for token in contents.split_whitespace() {
+            match (token.strip_prefix('+'), commands.last_mut()) {
+                (Some(command), _) => commands.push((command.to_string(), vec![])),
+                (None, Some((_, args))) => args.push(token.to_string()),
+                (None, None) => ()
+            }
+        },
+        false => for line in contents.lines() {
+            let mut tokens = line.split_whitespace().map(|x| x.to_string());
+            if let Some(command) = tokens.next() {
+                commands.push((command, tokens.collect()));
+            }
+        }
+    }
+
+    commands
+}
+
+fn parse_json(contents: &str) -> Result<Vec<DownloadItem>, String> {
+    let manifest: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|err| format!("Invalid json: {}", err))?;
+    let mut items = vec![];
+
+    for app in manifest["apps"].as_array().into_iter().flatten() {
+        let app_id = match app["app_id"].as_str() {
+            Some(app_id) => app_id,
+            None => return Err(String::from("App without an app_id"))
+        };
+
+        for item in app["items"].as_array().into_iter().flatten() {
+            match item["id"].as_u64() {
+                Some(id) => items.push(DownloadItem {
+                    app_id: app_id.to_string(), item_id: ItemId(id), title: item["title"].as_str().map(|x| x.to_string()),
+                    account: None, install_dir: None
+                }),
+                None => return Err(format!("Item without an id for app '{}'", app_id))
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::Source;
+
+    fn entries() -> Vec<QueueEntry> {
+        vec![
+            QueueEntry::new(String::from("294100"), String::from("rimworld"), ItemId(111), Some(String::from("Hospitality")), Source::Manual),
+            QueueEntry::new(String::from("294100"), String::from("rimworld"), ItemId(222), None, Source::Manual),
+            QueueEntry::new(String::from("107410"), String::from("arma3"), ItemId(333), Some(String::from("CBA_A3")), Source::Manual),
+        ]
+    }
+
+    /// Renders `format` and reads the items back out of it
+    fn round_trip(
+        format: ExportFormat, accounts: &HashMap<String, String>, install_dirs: &HashMap<String, PathBuf>
+    ) -> (String, Vec<DownloadItem>) {
+        let entries = entries();
+        let refs: Vec<&QueueEntry> = entries.iter().collect();
+        let content = format.render(&refs, accounts, install_dirs);
+
+        let items = parse_export(&content).unwrap();
+        (content, items)
+    }
+
+    fn ids(items: &[DownloadItem]) -> Vec<(&str, u64)> {
+        let mut ids: Vec<(&str, u64)> = items.iter().map(|x| (x.app_id.as_str(), x.item_id.0)).collect();
+        ids.sort();
+        ids
+    }
+
+    fn item(items: &[DownloadItem], id: u64) -> &DownloadItem {
+        items.iter().find(|x| x.item_id == ItemId(id)).unwrap()
+    }
+
+    const ALL: [(&str, u64); 3] = [("107410", 333), ("294100", 111), ("294100", 222)];
+
+    #[test]
+    fn steamcmd_args_round_trip() {
+        let (content, items) = round_trip(ExportFormat::SteamcmdArgs, &HashMap::new(), &HashMap::new());
+
+        assert!(content.starts_with("+login anonymous "));
+        assert!(content.ends_with(" +quit"));
+        assert_eq!(ids(&items), ALL);
+        assert!(items.iter().all(|x| x.account.is_none() && x.install_dir.is_none()));
+    }
+
+    #[test]
+    fn runscript_round_trip() {
+        let (content, items) = round_trip(ExportFormat::Runscript, &HashMap::new(), &HashMap::new());
+
+        assert!(content.contains("\nlogin anonymous\nworkshop_download_item 294100 111\n"));
+        assert_eq!(ids(&items), ALL);
+        assert!(items.iter().all(|x| x.account.is_none() && x.install_dir.is_none()));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let (_, items) = round_trip(ExportFormat::Json, &HashMap::new(), &HashMap::new());
+
+        assert_eq!(ids(&items), ALL);
+        assert_eq!(item(&items, 111).title.as_deref(), Some("Hospitality"));
+        assert_eq!(item(&items, 222).title, None);
+    }
+
+    #[test]
+    fn accounts_and_install_dirs_round_trip() {
+        let accounts = HashMap::from([(String::from("107410"), String::from("user"))]);
+        let install_dirs = HashMap::from([(String::from("107410"), PathBuf::from("/srv/arma 3"))]);
+
+        let (content, items) = round_trip(ExportFormat::SteamcmdArgs, &accounts, &install_dirs);
+        assert!(content.contains("+force_install_dir /srv/arma 3 +login user +workshop_download_item 107410 333"));
+        assert_eq!(ids(&items), ALL);
+        assert_eq!(item(&items, 333).account.as_deref(), Some("user"));
+        assert_eq!(item(&items, 333).install_dir, Some(PathBuf::from("/srv/arma 3")));
+        assert_eq!(item(&items, 111).account, None);
+        assert_eq!(item(&items, 111).install_dir, None);
+
+        let (content, items) = round_trip(ExportFormat::Runscript, &accounts, &install_dirs);
+        assert!(content.contains("force_install_dir \"/srv/arma 3\"\nlogin user\nworkshop_download_item 107410 333\n"));
+        assert!(content.contains("@NoPromptForPassword 0"));
+        assert_eq!(ids(&items), ALL);
+        assert_eq!(item(&items, 333).account.as_deref(), Some("user"));
+        assert_eq!(item(&items, 333).install_dir, Some(PathBuf::from("/srv/arma 3")));
+        assert_eq!(item(&items, 222).account, None);
+    }
+
+    #[test]
+    fn invalid_exports() {
+        assert!(parse_export("+login anonymous +workshop_download_item 294100").is_err());
+        assert!(parse_export("+login anonymous +workshop_download_item 294100 +quit").is_err());
+        assert!(parse_export("+workshop_download_item rimworld 111 +quit").is_err());
+        assert!(parse_export("login anonymous\nworkshop_download_item 294100\nquit\n").is_err());
+        assert!(parse_export("{ not json").is_err());
+        assert!(parse_export("+login anonymous +quit").unwrap().is_empty());
+    }
+}
@@ -1,69 +1,62 @@
-use std::io::{self, stdin, Write};
+use std::env;
 use std::process::exit;
 
-use crate::utils::{log, LogLevel};
-use crate::commands::Command;
-
-use crate::commands::alias::AliasCommand;
-use crate::commands::search::SearchCommand;
-use crate::commands::help::HelpCommand;
-use crate::commands::convert::ConvertCommand;
+use utils::ErrorKind;
 
 const VERSION: f32 = 1.0;
-const STEAMCMD_DIR: &str = r#"C:/Users/user/Desktop/steamcmd/steamcmd.exe"#;
 
 mod cli;
 mod manager;
 mod utils;
 mod commands;
+mod repl;
+mod queue;
+mod export;
+mod steamcmd;
+mod download;
+mod downloader;
+mod workshop;
+mod api;
 
 
 fn main() {
     let mut manager = manager::Manager::new();
-    manager.config.load_config();
+    if let Err(kind) = manager.config.load_config() {
+        exit(kind.exit_code());
+    }
 
-    loop {
-        let mut buf = String::new();
+    // Ctrl-C cancels a running download and returns to the prompt, anything else
+    // is stopped like before. The queue is saved whenever it changes, so nothing is lost.
+    let _ = ctrlc::set_handler(|| {
+        if !download::cancel() {
+            exit(ErrorKind::Cancelled.exit_code());
+        }
+    });
+
+    // Anything passed to the process is run as a single command, e.g.
+    // `rsw search rimworld "hospital" --pages 2 --select all`
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        // `rsw --script file.rsw` is the same as `rsw run file.rsw`
+        if args[0] == "--script" {
+            args[0] = String::from("run");
+        }
 
-        print!("RSWC (v{:?})> ", VERSION);
-        
-        io::stdout().flush().unwrap();
-        stdin().read_line(&mut buf).unwrap();
+        let code = match commands::execute(&mut manager, args) {
+            Ok(_) => 0,
+            Err(kind) => kind.exit_code()
+        };
 
-        let input_parser = cli::InputParser::new(buf);
+        manager.save();
+        exit(code);
+    }
 
-        match input_parser.command.as_str().trim() {
-            "help" => HelpCommand::new(&mut manager.config,input_parser).run(),
-            "search" => {
-                let (app_id, items) = SearchCommand::new(&mut manager.config, input_parser).run();
-                manager.add_items(app_id, items);
-            },
-            "convert" => {
-                let (app_id, items) = ConvertCommand::new(&mut manager.config, input_parser).run();
-                manager.add_items(app_id, items);
-            }
-            "export" => {
-                manager.export();
-            }
-            "download" => {
-                manager.download(input_parser);
-            },
-            "aliases" => {
-                AliasCommand::new(&mut manager.config, input_parser).run();
-            },
-            "exit" => {
-                log(
-                    LogLevel::EXIT, 
-                    format!("Saving and exitting...")
-                );
-                manager.save();
-                
-                exit(0);
-            }
-            _ => log(
-                LogLevel::ERR, 
-                format!("'{}' is not a valid command", input_parser.command.trim())
-            )
-        }
+    // only downloads need steamcmd, so a missing one is reported but not fatal.
+    // One-shot runs report it when they get to downloading.
+    let configured = manager.config.get_props_ref().and_then(|props| props.steamcmd.clone());
+    if let Err(err) = steamcmd::locate(None, configured.as_deref()) {
+        utils::log(utils::LogLevel::WARN, err);
     }
+
+    repl::run(&mut manager);
 }
@@ -1,152 +1,946 @@
-use std::{collections::HashMap, fs::File, io::Write, io::Read, vec, process::Command, path::Path};
-use rand::distributions::{Alphanumeric, DistString};
-use scraper::ElementRef;
+use std::{collections::{HashMap, HashSet}, fs::OpenOptions, io::Write, vec, path::PathBuf, str::FromStr, sync::Arc};
 use serde::{Deserialize, Serialize};
 
-use crate::{utils::{underscorize, log, LogLevel}, STEAMCMD_DIR, cli::InputParser};
+use crate::{utils::{self, underscorize, format_bytes, log, LogLevel, ErrorKind}, cli::InputParser};
+use crate::queue::{ItemId, Queue, QueueEntry, Source, Status, StatusFilter, TitledItems};
+use crate::export::{self, ExportFormat, ExportOptions};
+use crate::steamcmd::{self, DownloadItem, ItemResult, Progress};
+use crate::download::{self, Event, ProgressLine};
+use crate::workshop::{self, Cache, Collection, ItemDetails, MetadataSource, RequiredDlc};
+use crate::api;
+use crate::downloader::{
+    Downloader, DownloaderKind, SteamcmdDownloader, DepotDownloader, MockDownloader, MockScript, MOCK_SCRIPT_ENV_VAR
+};
+
+const QUEUE_FILE: &str = "queue.json";
+const CACHE_FILE: &str = "cache.json";
+const CONFIG_FILE: &str = "config.json";
+
+const DEFAULT_BATCH_SIZE: usize = 50;
+const DEFAULT_RETRIES: u32 = 2;
+/// Seconds before the first retry, doubled for every retry after it
+const DEFAULT_RETRY_DELAY: u64 = 10;
+const DEFAULT_JOBS: usize = 1;
+/// Seconds steamcmd may go without output before it is killed
+const DEFAULT_WATCHDOG: u64 = 600;
+
+/// A required item found while resolving dependencies, with the item that requires it
+struct Dependency {
+    app_id: String,
+    id: ItemId,
+    title: String,
+    required_by: ItemId,
+    required_by_title: String
+}
+
+/// State of a dependency walk, see `Manager::resolve_dependencies`
+#[derive(Default)]
+struct Resolution {
+    visited: HashSet<ItemId>,
+    /// Items from the root to the item being visited, to tell cycles apart from shared dependencies
+    path: Vec<ItemId>,
+    dependencies: Vec<Dependency>,
+    dlc: Vec<(String, RequiredDlc)>
+}
 
 pub struct Manager {
-    workshop: HashMap<String, (String, Vec<usize>)>,
-    pub config: Config
+    pub queue: Queue,
+    pub config: Config,
+    pub cache: Cache
 }
 
 impl Manager {
     pub fn new() -> Self {
         Manager {
-            workshop: HashMap::new(),
-            config: Config::new()
+            queue: Queue::load(QUEUE_FILE),
+            config: Config::new(),
+            cache: Cache::load(CACHE_FILE)
+        }
+    }
+
+    pub fn save_queue(&self) {
+        self.queue.save(QUEUE_FILE);
+    }
+
+    /// `--source` when given, otherwise `metadata_source` from the config, the pages by default
+    pub fn metadata_source(&self, input: &InputParser) -> Result<MetadataSource, ErrorKind> {
+        let name = input.options.get("--source").cloned()
+            .or_else(|| self.config.get_props_ref().and_then(|x| x.metadata_source.clone()));
+
+        match name.map(|x| x.parse::<MetadataSource>()) {
+            Some(Ok(source)) => Ok(source),
+            Some(Err(err)) => {
+                log(LogLevel::ERR, err);
+                Err(ErrorKind::Usage)
+            },
+            None => Ok(MetadataSource::Page)
+        }
+    }
+
+    /// The Steam Web API client, with the key and base url from the environment or the config
+    pub fn api_client(&self) -> api::Client {
+        let props = self.config.get_props_ref();
+        let from_env = |name: &str| std::env::var(name).ok().filter(|x| !x.trim().is_empty());
+
+        let key = from_env(api::KEY_ENV_VAR).or_else(|| props.and_then(|x| x.api_key.clone()));
+        let url = from_env(api::URL_ENV_VAR)
+            .or_else(|| props.and_then(|x| x.api_url.clone()))
+            .unwrap_or_else(|| String::from(api::DEFAULT_URL));
+
+        api::Client::new(url, key)
+    }
+
+    /// A collection from `source`. `Ok(None)` when `id` isn't a collection.
+    pub fn fetch_collection(&self, id: ItemId, source: MetadataSource) -> Result<Option<Collection>, String> {
+        match source {
+            MetadataSource::Page => workshop::fetch_collection(&self.config.community_url(), id),
+            MetadataSource::Api => self.api_client().collection(id)
+        }
+    }
+
+    /// The details of an item from the cache, or from `source` when it isn't
+    /// cached yet or `refresh` is set. Queued items without a title get it.
+    pub fn item_details(&mut self, id: ItemId, refresh: bool, source: MetadataSource) -> Result<ItemDetails, ErrorKind> {
+        if !refresh {
+            if let Some(details) = self.cache.get(id) {
+                return Ok(details.clone());
+            }
+        }
+
+        let fetched = match source {
+            MetadataSource::Page => workshop::fetch_item(&self.config.community_url(), id),
+            MetadataSource::Api => self.api_client().item_details(id)
+        };
+
+        let details = match fetched {
+            Ok(Some(details)) => details,
+            Ok(None) => {
+                log(
+                    LogLevel::ERR,
+                    format!("No workshop item with the id '{}'", id)
+                );
+                return Err(ErrorKind::Usage);
+            },
+            Err(err) => {
+                log(LogLevel::ERR, err);
+                return Err(ErrorKind::Network);
+            }
+        };
+
+        self.cache.insert(details.clone());
+        self.cache.save(CACHE_FILE);
+
+        if self.queue.fill_title(id, &details.title) > 0 {
+            self.save_queue();
         }
+
+        Ok(details)
     }
 
-    pub fn add_items(&mut self, app_id: String, items: Vec<usize>) {
-        if items.len() == 0 {
+    pub fn add_items(&mut self, app_id: String, items: TitledItems, source: Source) {
+        if items.is_empty() {
             return;
         }
         
         let items_len = items.len();
         let name = self.config.get_props_ref()
-                .unwrap()
-                .get_name_by_app_id(app_id.clone())
-                .unwrap();
+                .and_then(|props| props.get_name_by_app_id(app_id.clone()))
+                .unwrap_or(app_id.clone());
+
+        let mut added: usize = 0;
+        for (item_id, title) in items {
+            let title = title.or_else(|| self.cache.title(item_id));
+            let entry = QueueEntry::new(app_id.clone(), name.clone(), item_id, title, source.clone());
 
-        if self.workshop.contains_key(&app_id) {
-            self.workshop.get_mut(&app_id).unwrap().1.extend(items);
+            if self.queue.add(entry) {
+                added += 1;
+            }
         }
 
-        else {
-            self.workshop.insert(app_id.clone(), (name.to_owned(), items));
+        self.save_queue();
+
+        if added < items_len {
+            log(
+                LogLevel::INFO, 
+                format!("Skipped {} items that were already added", items_len - added)
+            );
         }
 
         log(
             LogLevel::SUCCESS, 
-            format!("Added {} items for '{}'", items_len, name)
+            format!("Added {} items for '{}'", added, name)
+        )
+    }
+
+    /// Adds `items` like `add_items`, followed by the items they require and the
+    /// items those require in turn, after listing which item pulled in which.
+    /// Required items are read from `metadata`.
+    pub fn add_items_with_deps(
+        &mut self, app_id: String, items: TitledItems, source: Source, metadata: MetadataSource
+    ) {
+        let roots: Vec<ItemId> = items.iter().map(|(id, _)| *id).collect();
+        self.add_items(app_id.clone(), items, source);
+
+        let resolution = self.resolve_dependencies(&app_id, &roots, metadata);
+        if resolution.dependencies.is_empty() && resolution.dlc.is_empty() {
+            log(
+                LogLevel::INFO,
+                String::from("No required items found")
+            );
+            return;
+        }
+
+        Manager::report_dependencies(&resolution);
+
+        let mut added: usize = 0;
+        for dependency in resolution.dependencies.iter() {
+            let name = self.config.get_props_ref()
+                .and_then(|props| props.get_name_by_app_id(dependency.app_id.clone()))
+                .unwrap_or(dependency.app_id.clone());
+            let entry = QueueEntry::new(
+                dependency.app_id.clone(), name, dependency.id, Some(dependency.title.clone()),
+                Source::Dependency { of: dependency.required_by }
+            );
+
+            if self.queue.add(entry) {
+                added += 1;
+            }
+        }
+
+        self.save_queue();
+
+        if added < resolution.dependencies.len() {
+            log(
+                LogLevel::INFO,
+                format!("Skipped {} required items that were already added", resolution.dependencies.len() - added)
+            );
+        }
+
+        log(
+            LogLevel::SUCCESS,
+            format!("Added {} required items", added)
         )
     }
 
-    pub fn export(&mut self) -> Vec<String> {
-        let mut contents: Vec<String> = vec![String::from("+login"), String::from("anonymous")];
-        let mut app_names: Vec<String> = vec![];
+    /// Walks the required items of `roots` depth first, visiting every item
+    /// once. An item requiring one of the items on the way to it is a cycle,
+    /// which is reported and not followed.
+    fn resolve_dependencies(&mut self, app_id: &str, roots: &[ItemId], metadata: MetadataSource) -> Resolution {
+        let mut resolution = Resolution { visited: roots.iter().copied().collect(), ..Resolution::default() };
+
+        for root in roots.iter() {
+            self.visit_dependencies(app_id, *root, metadata, &mut resolution);
+        }
+
+        resolution
+    }
+
+    fn visit_dependencies(&mut self, app_id: &str, id: ItemId, metadata: MetadataSource, resolution: &mut Resolution) {
+        // the error is already logged, the rest of the tree can still be resolved
+        let details = match self.item_details(id, false, metadata) {
+            Ok(details) => details,
+            Err(_) => return
+        };
 
-        if self.workshop.len() == 0 {
-            return vec![];
+        for dlc in details.required_dlc.iter() {
+            resolution.dlc.push((details.display_title(), dlc.clone()));
         }
 
-        for (app_id, (app_name, item_ids)) in self.workshop.iter() {
-            app_names.push(app_name.clone());
+        resolution.path.push(id);
+        for required in details.required_items.iter() {
+            if let Some(start) = resolution.path.iter().position(|x| *x == required.id) {
+                let cycle: Vec<String> = resolution.path[start..].iter()
+                    .chain(std::iter::once(&required.id))
+                    .map(|x| x.to_string())
+                    .collect();
+                log(
+                    LogLevel::WARN,
+                    format!("Skipping circular requirement {}", cycle.join(" -> "))
+                );
+                continue;
+            }
 
-            for id in item_ids.iter() {
-                contents.push(format!("+workshop_download_item"));
-                contents.push(app_id.to_string());
-                contents.push(id.to_string())
+            if !resolution.visited.insert(required.id) {
+                continue;
             }
+
+            resolution.dependencies.push(Dependency {
+                app_id: details.app_id.clone().unwrap_or(app_id.to_string()),
+                id: required.id,
+                title: required.title.clone(),
+                required_by: id,
+                required_by_title: details.display_title()
+            });
+            self.visit_dependencies(app_id, required.id, metadata, resolution);
         }
+        resolution.path.pop();
+    }
 
-        let rand_string = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
-        let mut file = File::create(format!(
-            "{}-{}.txt", app_names.join("+"), rand_string
-        )).unwrap();
+    fn report_dependencies(resolution: &Resolution) {
+        println!("{}", "-".repeat(60));
+        println!("Required items");
+        println!("{}", "-".repeat(60));
 
-        contents.push(String::from("+quit"));
-        file.write_all(contents.join(" ").as_bytes()).unwrap();
+        for dependency in resolution.dependencies.iter() {
+            println!(
+                "{0: <40} | required by {1}",
+                format!("{} [{}]", dependency.title, dependency.id), dependency.required_by_title
+            );
+        }
 
+        for (required_by, dlc) in resolution.dlc.iter() {
+            println!(
+                "{0: <40} | DLC required by {1}",
+                format!("{} ({})", dlc.name, dlc.app_id), required_by
+            );
+        }
 
-        contents
+        println!("{}", "-".repeat(60));
     }
 
-    pub fn download(&mut self, input: InputParser) {
-        let mut data: Vec<String> = vec![];
+    /// Writes the items matching `options.filter` to `options.out`, or a path
+    /// derived from the app names. Items log in with `options.account`, or the
+    /// account of their alias. Returns the path that was written, if there was anything to export.
+    pub fn export(&mut self, options: ExportOptions) -> Result<Option<String>, ErrorKind> {
+        let ExportOptions { filter, format, out, random_name, account, install_dir, dry_run } = options;
+        let entries = self.queue.select(&filter);
+        if entries.is_empty() {
+            log(
+                LogLevel::INFO,
+                String::from("No items to export")
+            );
+            return Ok(None);
+        }
 
-        if input.options.contains_key("--file") {
-            let file = File::open(input.options.get("--file").unwrap());
+        let app_ids: Vec<&str> = entries.iter().map(|x| x.app_id.as_str()).collect();
+        let usernames: HashMap<String, String> = self.accounts(&app_ids, account)?
+            .into_iter()
+            .map(|(app_id, (_, username))| (app_id, username))
+            .collect();
 
-            match file {
-                Ok(mut f) => {
-                    let mut buf = String::new();
-                    f.read_to_string(&mut buf).unwrap();
+        let path = match out {
+            Some(out) => out.clone(),
+            None => format.default_path(&entries, random_name)
+        };
 
-                    data.extend(
-                        buf.split_whitespace().map(|x| x.to_string()).collect::<Vec<String>>()
-                    )
-                },
-                Err(_) => log(
-                    LogLevel::ERR, 
-                    format!("File with the path '{}' does not exist", 
-                        input.options.get("--file").unwrap()
-                    )
-                )
+        let install_dirs = self.install_dirs(&app_ids, install_dir);
+
+        let content = format.render(&entries, &usernames, &install_dirs);
+
+        if dry_run {
+            log(
+                LogLevel::INFO,
+                format!("Dry run, would write {} items to '{}'", entries.len(), path)
+            );
+
+            // where steamcmd puts the items when the export is run next to it
+            let steamcmd = steamcmd::locate(None, self.config.get_props_ref().and_then(|x| x.steamcmd.as_deref()))
+                .map(|program| SteamcmdDownloader { program });
+            Manager::print_apps(&app_ids, |app_id| match (&steamcmd, install_dirs.get(app_id)) {
+                (Ok(steamcmd), dir) => steamcmd.content_dir(dir, app_id).display().to_string(),
+                (Err(_), Some(dir)) => dir.join("steamapps/workshop/content").join(app_id).display().to_string(),
+                (Err(_), None) => format!("<steamcmd dir>/steamapps/workshop/content/{}", app_id)
+            });
+
+            println!("{}", content.trim_end());
+            return Ok(Some(path));
+        }
+
+        if std::fs::write(&path, content).is_err() {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't write to '{}'", path)
+            );
+            return Err(ErrorKind::Io);
+        }
+
+        log(
+            LogLevel::SUCCESS,
+            format!("Exported {} items to '{}'", entries.len(), path)
+        );
+
+        Ok(Some(path))
+    }
+
+    pub fn download(&mut self, input: InputParser) -> Result<(), ErrorKind> {
+        let items: Vec<DownloadItem>;
+        let dry_run = input.has_flag("--dry-run");
+
+        if input.options.contains_key("--file") {
+            let path = input.options.get("--file").unwrap();
+            let buf = match std::fs::read_to_string(path) {
+                Ok(buf) => buf,
+                Err(_) => {
+                    log(
+                        LogLevel::ERR, 
+                        format!("File with the path '{}' does not exist", path)
+                    );
+                    return Err(ErrorKind::Io);
+                }
+            };
+
+            items = match export::parse_export(&buf) {
+                Ok(items) => items.into_iter()
+                    .map(|item| DownloadItem { title: item.title.clone().or_else(|| self.cache.title(item.item_id)), ..item })
+                    .collect(),
+                Err(err) => {
+                    log(
+                        LogLevel::ERR,
+                        format!("Couldn't read items from '{}': {}", path, err)
+                    );
+                    return Err(ErrorKind::Usage);
+                }
             };
         }
 
         else {
-            data.extend(self.export())
+            let filter: StatusFilter = match input.options["--status"].parse() {
+                Ok(filter) => filter,
+                Err(err) => {
+                    log(LogLevel::ERR, err);
+                    return Err(ErrorKind::Usage);
+                }
+            };
+
+            items = self.queue.select(&filter).into_iter().map(DownloadItem::from).collect();
+            if !dry_run {
+                self.export(ExportOptions {
+                    filter,
+                    format: ExportFormat::SteamcmdArgs,
+                    out: None,
+                    random_name: true,
+                    account: input.options.get("--account"),
+                    install_dir: input.options.get("--install-dir"),
+                    dry_run: false,
+                })?;
+            }
         }
 
-        if data.len() == 0 {
+        if items.is_empty() {
             log(
                 LogLevel::INFO,
-                format!("No items to download")
+                String::from("No items to download")
             );
-            return;
+            return Ok(());
         }
 
-        // we div. by 3, since for each item 2 more strings get pushed
-        let data_len = (data.len() / 3) - 1;
-        let mut command = Command::new(STEAMCMD_DIR);
-        
-        command.args(data);
-        match command.output() {
-            Ok(_res) => {
+        let app_ids: Vec<&str> = items.iter().map(|x| x.app_id.as_str()).collect();
+
+        // an export keeps its own logins and install dirs, only the options override them
+        let from_file = input.options.contains_key("--file");
+        let (account, install_dir) = (input.options.get("--account"), input.options.get("--install-dir"));
+        let accounts = match (from_file, account) {
+            (true, None) => HashMap::new(),
+            _ => self.accounts(&app_ids, account)?
+        };
+        let install_dirs = match (from_file, install_dir) {
+            (true, None) => HashMap::new(),
+            _ => self.install_dirs(&app_ids, install_dir)
+        };
+
+        let items: Vec<DownloadItem> = items.into_iter()
+            .map(|item| DownloadItem {
+                account: accounts.get(&item.app_id).map(|(_, username)| username.clone()).or(item.account),
+                install_dir: install_dirs.get(&item.app_id).cloned().or(item.install_dir),
+                ..item
+            })
+            .collect();
+
+        let logins = self.logins(&items);
+        let passwords = match dry_run {
+            true => logins.keys().map(|username| (username.clone(), String::from("<password>"))).collect(),
+            false => Manager::passwords(&logins)?
+        };
+
+        let props = self.config.get_props_ref();
+        let batch_size = match input.options.get("--batch-size") {
+            Some(value) => value.parse::<usize>().unwrap_or(0),
+            None => props.map_or(DEFAULT_BATCH_SIZE, |x| x.batch_size)
+        };
+        let retries = match input.options.get("--retries") {
+            Some(value) => Manager::non_negative("--retries", value)?,
+            None => props.map_or(DEFAULT_RETRIES, |x| x.retries)
+        };
+        let jobs = match input.options.get("--jobs") {
+            Some(value) => value.parse::<usize>().unwrap_or(0),
+            None => props.map_or(DEFAULT_JOBS, |x| x.jobs)
+        };
+
+        for (name, value) in [("--batch-size", batch_size), ("--jobs", jobs)] {
+            if value == 0 {
                 log(
-                    LogLevel::SUCCESS, 
-                    format!("Downloaded {} items", data_len)
+                    LogLevel::ERR,
+                    format!("{} must be at least 1", name)
                 );
+                return Err(ErrorKind::Usage);
+            }
+        }
+
+        let downloader = self.downloader(&input)?;
+
+        let options = download::Options {
+            downloader,
+            batch_size,
+            retries,
+            retry_delay: props.map_or(DEFAULT_RETRY_DELAY, |x| x.retry_delay),
+            jobs,
+            watchdog: match input.options.get("--timeout") {
+                Some(value) => Manager::non_negative("--timeout", value)?,
+                None => props.map_or(DEFAULT_WATCHDOG, |x| x.watchdog)
+            },
+            passwords,
+        };
 
-                if _res.stderr.len() > 0 {
+        if dry_run {
+            Manager::print_plan(&items, &options);
+            return Ok(());
+        }
+
+        // opened before any status changes so a bad path leaves the queue as it was
+        let mut tee = match input.options.get("--log") {
+            Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => Some(file),
+                Err(_) => {
                     log(
-                        LogLevel::INFO,
-                        String::from_utf8(_res.stderr).unwrap()
-                    )
+                        LogLevel::ERR,
+                        format!("Couldn't open '{}' for logging", path)
+                    );
+                    return Err(ErrorKind::Io);
                 }
             },
-            Err(_err) => {
-                log(
-                    LogLevel::ERR, 
-                    format!("Couldn't download items")
-                );
-                log(
-                    LogLevel::ERR, 
-                    format!("Cause: {}", _err.kind())
-                );
+            None => None
+        };
+
+        let refs: Vec<&DownloadItem> = items.iter().collect();
+        self.set_statuses(&refs, Status::Downloading);
+
+        let total = items.len();
+        let mut outcomes: Vec<(DownloadItem, ItemResult, u32)> = vec![];
+        let mut aborted: Option<String> = None;
+        let mut progress = ProgressLine::new(total, jobs);
+
+        for event in download::start(items.clone(), options) {
+            progress.clear();
+
+            match event {
+                Event::Started { job, items, attempt } if jobs > 1 => log(
+                    LogLevel::INFO,
+                    format!("Job {} downloading {} items (attempt {} of {})", job, items, attempt, retries + 1)
+                ),
+                Event::Started { .. } => (),
+                Event::Output { job, line } => {
+                    if let Some(file) = tee.as_mut() {
+                        let written = match jobs {
+                            1 => writeln!(file, "{}", line),
+                            _ => writeln!(file, "[job {}] {}", job, line)
+                        };
+
+                        if written.is_err() {
+                            log(
+                                LogLevel::WARN,
+                                String::from("Couldn't write to the log file, no longer logging")
+                            );
+                            tee = None;
+                        }
+                    }
+
+                    match steamcmd::parse_progress(&line) {
+                        Some(Progress::Item(item_id)) => progress.set_item(job, match items.iter().find(|x| x.item_id == item_id) {
+                            Some(item) => item.to_string(),
+                            None => format!("[{}]", item_id)
+                        }),
+                        Some(Progress::Bytes { done, total }) => progress.set_bytes(job, done, total),
+                        None => ()
+                    }
+                },
+                Event::Prompt { job, text, reply } => {
+                    let text = match jobs {
+                        1 => format!("{} ", text.trim()),
+                        _ => format!("[job {}] {} ", job, text.trim())
+                    };
+
+                    // passwords aren't echoed, Steam Guard codes are
+                    let answer = match text.contains("password") {
+                        true => rpassword::prompt_password(text).unwrap_or_default(),
+                        false => {
+                            print!("{}", text);
+                            let _ = std::io::stdout().flush();
+
+                            let mut answer = String::new();
+                            let _ = std::io::stdin().read_line(&mut answer);
+                            answer
+                        }
+                    };
+
+                    let _ = reply.send(answer.trim().to_string());
+                },
+                Event::Retrying { job, items, delay, attempt } => log(
+                    LogLevel::WARN,
+                    match jobs {
+                        1 => format!("Retrying {} failed items in {}s (attempt {} of {})", items, delay, attempt, retries + 1),
+                        _ => format!("Job {} retrying {} failed items in {}s (attempt {} of {})", job, items, delay, attempt, retries + 1)
+                    }
+                ),
+                // items that are going to be retried stay downloading
+                Event::Finished { last: false, .. } => (),
+                Event::Finished { job, item, result, attempt, .. } => {
+                    let status = match &result {
+                        ItemResult::Downloaded { .. } => Status::Downloaded,
+                        ItemResult::Failed { reason } => Status::Failed { reason: reason.clone() },
+                        ItemResult::Cancelled => Status::Cancelled
+                    };
+                    self.queue.set_status(&item.app_id, item.item_id, status.clone());
+                    self.save_queue();
+
+                    progress.finish_item(job);
+                    outcomes.push((item, result, attempt));
+                    log(
+                        LogLevel::INFO,
+                        format!("({}/{}) {} {}", outcomes.len(), total, outcomes.last().unwrap().0, status.name())
+                    );
+                },
+                Event::Aborted { reason } => aborted = Some(reason)
+            }
+
+            progress.draw();
+        }
+
+        progress.clear();
+
+        // items of batches that never started
+        let remaining: Vec<&DownloadItem> = items.iter()
+            .filter(|x| !outcomes.iter().any(|(item, _, _)| item.item_id == x.item_id && item.app_id == x.app_id))
+            .collect();
+
+        if let Some(reason) = aborted {
+            // retrying won't help if steamcmd can't be started at all
+            log(
+                LogLevel::ERR, 
+                String::from("Couldn't download items")
+            );
+            log(
+                LogLevel::ERR, 
+                format!("Cause: {}", reason)
+            );
+
+            self.set_statuses(&remaining, Status::Failed { reason });
+            return Err(ErrorKind::Steamcmd);
+        }
+
+        if download::cancelled() {
+            self.set_statuses(&remaining, Status::Pending);
+
+            if !outcomes.is_empty() {
+                Manager::report(&outcomes);
+            }
+
+            log(
+                LogLevel::WARN,
+                format!("Download cancelled, {} items that hadn't started are pending again", remaining.len())
+            );
+            return Err(ErrorKind::Cancelled);
+        }
+
+        match Manager::report(&outcomes) {
+            0 => Ok(()),
+            _ => Err(ErrorKind::Steamcmd)
+        }
+    }
+
+    /// Parses the value of a number option that can't be negative
+    fn non_negative<T: FromStr>(name: &str, value: &str) -> Result<T, ErrorKind> {
+        value.parse::<T>().map_err(|_| {
+            log(
+                LogLevel::ERR,
+                format!("{} must be 0 or more, got '{}'", name, value)
+            );
+            ErrorKind::Usage
+        })
+    }
+
+    /// Prints the processes `download` would start for `items`, batch by batch
+    fn print_plan(items: &[DownloadItem], options: &download::Options) {
+        let (batches, jobs) = download::plan(items, options);
+        let downloader = &options.downloader;
+        log(
+            LogLevel::INFO,
+            format!(
+                "Dry run, {} would download {} items in {} batches on {} jobs",
+                downloader.name(), items.len(), batches.len(), jobs
+            )
+        );
+
+        let app_ids: Vec<&str> = items.iter().map(|x| x.app_id.as_str()).collect();
+        Manager::print_apps(&app_ids, |app_id| match (items.iter().find(|x| x.app_id == app_id).and_then(|x| x.install_dir.as_ref()), jobs) {
+            (Some(dir), _) => downloader.content_dir(Some(dir), app_id).display().to_string(),
+            (None, 1) => downloader.content_dir(None, app_id).display().to_string(),
+            (None, _) => format!(
+                "{} (job-1 to job-{})",
+                downloader.content_dir(Some(&download::job_dir(1)), app_id).display(), jobs
+            )
+        });
+
+        for (index, batch) in batches.iter().enumerate() {
+            // batches go to whichever job is free, this is the order they start in
+            let job = index % jobs + 1;
+            let install_dir = download::install_dir(batch, job, jobs);
+
+            println!("Batch {}/{}, {} items on job {}:", index + 1, batches.len(), batch.len(), job);
+
+            let commands = downloader.commands(install_dir.as_ref(), batch, &options.passwords);
+            if commands.is_empty() {
+                println!("  nothing is started, {} downloads in-process", downloader.name());
+            }
+            for command in commands.iter() {
+                println!("  {}", utils::command_line(command));
+            }
+        }
+    }
+
+    /// Prints how many of `app_ids` there are per app, and where they end up
+    fn print_apps(app_ids: &[&str], content_dir: impl Fn(&str) -> String) {
+        let mut counts: Vec<(&str, usize)> = vec![];
+        for app_id in app_ids.iter() {
+            match counts.iter_mut().find(|(x, _)| x == app_id) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((app_id, 1))
+            }
+        }
+
+        println!("{}", "-".repeat(100));
+        for (app_id, count) in counts {
+            println!(
+                "{0: <12} | {1: >6} items | {2}",
+                app_id, count, content_dir(app_id)
+            );
+        }
+        println!("{}", "-".repeat(100));
+    }
+
+    /// The downloader picked with `--downloader` or in the config, steamcmd by default
+    fn downloader(&self, input: &InputParser) -> Result<Arc<dyn Downloader>, ErrorKind> {
+        let props = self.config.get_props_ref();
+        let name = input.options.get("--downloader").cloned()
+            .or_else(|| props.and_then(|x| x.downloader.clone()))
+            .unwrap_or_else(|| String::from("steamcmd"));
+
+        let kind = match name.parse::<DownloaderKind>() {
+            Ok(kind) => kind,
+            Err(err) => {
+                log(LogLevel::ERR, err);
+                return Err(ErrorKind::Usage);
+            }
+        };
+
+        let downloader: Result<Arc<dyn Downloader>, String> = match kind {
+            DownloaderKind::Steamcmd => steamcmd::locate(
+                input.options.get("--steamcmd").map(|x| x.as_str()), props.and_then(|x| x.steamcmd.as_deref())
+            ).map(|program| Arc::new(SteamcmdDownloader { program }) as Arc<dyn Downloader>),
+            DownloaderKind::DepotDownloader => DepotDownloader::locate(props.and_then(|x| x.depotdownloader.as_deref()))
+                .map(|program| Arc::new(DepotDownloader { program }) as Arc<dyn Downloader>),
+            DownloaderKind::Mock => match std::env::var(MOCK_SCRIPT_ENV_VAR).ok().or_else(|| props.and_then(|x| x.mock_script.clone())) {
+                Some(path) => MockDownloader::load(&path).map(|x| Arc::new(x) as Arc<dyn Downloader>),
+                None => Ok(Arc::new(MockDownloader::new(MockScript::default())))
+            }
+        };
+
+        downloader.map_err(|err| {
+            log(LogLevel::ERR, err);
+            ErrorKind::Steamcmd
+        })
+    }
+
+    /// Maps each of `app_ids` to the dir it is installed into: `explicit` for
+    /// all of them, otherwise the dir set for their alias. Apps without one are left out.
+    /// Relative dirs are made absolute, steamcmd resolves them against its own dir, not ours.
+    fn install_dirs(&self, app_ids: &[&str], explicit: Option<&String>) -> HashMap<String, PathBuf> {
+        let props = self.config.get_props_ref();
+        let cwd = std::env::current_dir().ok();
+
+        app_ids.iter()
+            .filter_map(|app_id| {
+                let dir = PathBuf::from(explicit.cloned()
+                    .or_else(|| props.and_then(|x| x.get_install_dir_by_app_id(app_id.to_string())))?);
+
+                let dir = match &cwd {
+                    Some(cwd) if dir.is_relative() => cwd.join(dir),
+                    _ => dir
+                };
+                Some((app_id.to_string(), dir))
+            })
+            .collect()
+    }
+
+    /// Maps each of `app_ids` to the account `(name, username)` it is
+    /// downloaded with: `explicit` for all of them, otherwise the account
+    /// set for their alias. Apps without an account are left out and log in anonymously.
+    fn accounts(
+        &self, app_ids: &[&str], explicit: Option<&String>
+    ) -> Result<HashMap<String, (String, String)>, ErrorKind> {
+        let props = match self.config.get_props_ref() {
+            Some(props) => props,
+            None => return Ok(HashMap::new())
+        };
+
+        let mut accounts = HashMap::new();
+        for app_id in app_ids.iter() {
+            let name = match explicit {
+                Some(name) => name.clone(),
+                None => match props.get_account_by_app_id(app_id.to_string()) {
+                    Some(name) => name,
+                    None => continue
+                }
+            };
+
+            match props.accounts.get(&name) {
+                Some(username) => accounts.insert(app_id.to_string(), (name, username.clone())),
+                None => {
+                    log(
+                        LogLevel::ERR,
+                        format!("No account named '{}', add it with 'accounts set {} (username)'", name, name)
+                    );
+                    return Err(ErrorKind::Usage);
+                }
+            };
+        }
+
+        Ok(accounts)
+    }
+
+    /// The usernames `items` log in with, mapped to `(name, username)` of
+    /// their account. Usernames from an export that aren't in the config are
+    /// their own name.
+    fn logins(&self, items: &[DownloadItem]) -> HashMap<String, (String, String)> {
+        let props = self.config.get_props_ref();
+
+        items.iter()
+            .filter_map(|x| x.account.clone())
+            .map(|username| {
+                let name = props
+                    .and_then(|x| x.accounts.iter().find(|(_, configured)| **configured == username))
+                    .map_or(username.clone(), |(name, _)| name.clone());
+
+                (username.clone(), (name, username))
+            })
+            .collect()
+    }
+
+    /// Reads the password of every account in `accounts` from its environment
+    /// variable, or asks for it. Passwords only live as long as the download.
+    fn passwords(accounts: &HashMap<String, (String, String)>) -> Result<HashMap<String, String>, ErrorKind> {
+        let mut passwords = HashMap::new();
+
+        for (name, username) in accounts.values() {
+            if passwords.contains_key(username) {
+                continue;
+            }
+
+            let password = match std::env::var(steamcmd::password_env_var(name)) {
+                Ok(password) => password,
+                Err(_) => match rpassword::prompt_password(
+                    format!("Password for '{}' ({}), empty to use steamcmd's saved login: ", name, username)
+                ) {
+                    Ok(password) => password,
+                    Err(err) => {
+                        log(
+                            LogLevel::ERR,
+                            format!("Couldn't read the password for '{}': {}", name, err)
+                        );
+                        return Err(ErrorKind::Io);
+                    }
+                }
+            };
+
+            passwords.insert(username.clone(), password);
+        }
+
+        Ok(passwords)
+    }
+
+    /// Prints the result of every item as a table, with how many attempts
+    /// it took. Returns how many items failed for good, cancelled items don't count.
+    fn report(outcomes: &[(DownloadItem, ItemResult, u32)]) -> usize {
+        println!("Item                                     | Result     | Tries | Size       | Path / Reason");
+
+        let (mut failed, mut cancelled, mut retried) = (0, 0, 0);
+        for (item, result, attempts) in outcomes.iter() {
+            match result {
+                ItemResult::Downloaded { path, bytes } => {
+                    if *attempts > 1 {
+                        retried += 1;
+                    }
+
+                    println!(
+                        "{0: <40} | downloaded | {1: <5} | {2: <10} | {3}",
+                        item.to_string(), attempts, bytes.map_or(String::from("-"), format_bytes), path
+                    );
+                },
+                ItemResult::Failed { reason } => {
+                    failed += 1;
+
+                    println!(
+                        "{0: <40} | failed     | {1: <5} | -          | {2}",
+                        item.to_string(), attempts, reason
+                    );
+                },
+                ItemResult::Cancelled => {
+                    cancelled += 1;
+
+                    println!(
+                        "{0: <40} | cancelled  | {1: <5} | -          | -",
+                        item.to_string(), attempts
+                    );
+                }
             }
         }
+
+        let downloaded = outcomes.len() - failed - cancelled;
+        let after_retry = match retried {
+            0 => String::new(),
+            _ => format!(" ({} after retrying)", retried)
+        };
+
+        match failed {
+            0 if cancelled > 0 => log(
+                LogLevel::WARN,
+                format!("Downloaded {} items{}, {} were cancelled", downloaded, after_retry, cancelled)
+            ),
+            0 => log(
+                LogLevel::SUCCESS,
+                format!("Downloaded {} items{}", downloaded, after_retry)
+            ),
+            _ => log(
+                LogLevel::ERR,
+                format!(
+                    "Downloaded {} of {} items{}, {} failed for good and were kept in the queue",
+                    downloaded, outcomes.len(), after_retry, failed
+                )
+            )
+        }
+
+        failed
+    }
+
+    fn set_statuses(&mut self, items: &[&DownloadItem], status: Status) {
+        if items.is_empty() {
+            return;
+        }
+
+        for item in items.iter() {
+            self.queue.set_status(&item.app_id, item.item_id, status.clone());
+        }
+
+        self.save_queue();
     }
 
     pub fn save(&self) {
+        self.save_queue();
+
         match &self.config.properties {
             Some(data) => {
                 let str_data = serde_json::to_string(&data).unwrap();
                 
-                std::fs::write("config.json", str_data).unwrap();
+                std::fs::write(CONFIG_FILE, str_data).unwrap();
             },
             None => unreachable!()
         }
@@ -173,63 +967,164 @@ impl Config {
         self.properties.as_mut()
     }
 
-    pub fn load_config(&mut self) {
-        let res = self.create_config_file();
+    /// Where the community pages are read from: `RSW_COMMUNITY_URL`, then
+    /// `community_url`, then steamcommunity.com
+    pub fn community_url(&self) -> String {
+        std::env::var(workshop::COMMUNITY_URL_ENV_VAR).ok()
+            .filter(|x| !x.trim().is_empty())
+            .or_else(|| self.get_props_ref().and_then(|x| x.community_url.clone()))
+            .unwrap_or_else(|| String::from(workshop::DEFAULT_COMMUNITY_URL))
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// Reads `config.json`, a missing or empty one is created with the defaults
+    pub fn load_config(&mut self) -> Result<(), ErrorKind> {
+        let text_data = match std::fs::read_to_string(CONFIG_FILE) {
+            Ok(text_data) => text_data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                log(
+                    LogLevel::ERR,
+                    format!("Couldn't read '{}': {}", CONFIG_FILE, err)
+                );
+                return Err(ErrorKind::Io);
+            }
+        };
 
-        if res.is_err() {
-            return;
-        }
+        if text_data.trim().is_empty() {
+            log(
+                LogLevel::INFO, 
+                format!("Creating '{}'", CONFIG_FILE)
+            );
 
-        let mut json_config_file = File::open("config.json").unwrap();
-        let mut text_data: String = String::new();
+            let props = ConfigProperties::new();
+            if let Err(err) = std::fs::write(CONFIG_FILE, serde_json::to_string(&props).unwrap()) {
+                log(
+                    LogLevel::ERR,
+                    format!("Couldn't write '{}': {}", CONFIG_FILE, err)
+                );
+                return Err(ErrorKind::Io);
+            }
 
-        json_config_file.read_to_string(&mut text_data).unwrap();
+            self.properties = Some(props);
+            return Ok(());
+        }
 
         // WIP
         // if config is invalid, prompt the user to reset or manually fix it themselves 
         let json_data: ConfigProperties = match serde_json::from_str(&text_data) {
             Ok(data) => data,
-            Err(_) => {
+            Err(err) => {
                 log(
                     LogLevel::ERR, 
-                    format!("Invalid json format for config")
+                    format!("Invalid json format for config: {}", err)
                 );
-                panic!("");
+                return Err(ErrorKind::Io);
             }
         };
 
         self.properties = Some(json_data);
-    }
-
-    fn create_config_file(&self) -> Result<(), ()> {
-        match Path::new("config.json").exists() {
-            true => Ok(()),
-            false => {
-                log(
-                    LogLevel::INFO, 
-                    format!("Creating log file")
-                );
-
-                if File::create("config.json").is_err() {
-                    return Err(())
-                }
-
-                Ok(())
-            }
-        }
+        Ok(())
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigProperties {
     #[serde(default)]
-    pub aliases: HashMap<String, String>
+    pub aliases: HashMap<String, String>,
+    /// How many items are passed to a single steamcmd run
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// How often items that failed are retried
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Seconds before the first retry
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    /// How many steamcmd processes download at the same time
+    #[serde(default = "default_jobs")]
+    pub jobs: usize,
+    /// Seconds without output from steamcmd before it is killed, 0 to disable
+    #[serde(default = "default_watchdog")]
+    pub watchdog: u64,
+    /// Steam accounts by name, as `name -> username`. Passwords are never stored.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub accounts: HashMap<String, String>,
+    /// The account each alias downloads with, as `alias -> account name`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub alias_accounts: HashMap<String, String>,
+    /// The dir each alias installs into (`force_install_dir`), as `alias -> dir`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub alias_install_dirs: HashMap<String, String>,
+    /// Path to the steamcmd executable, detected when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steamcmd: Option<String>,
+    /// steamcmd, depotdownloader or mock, steamcmd when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloader: Option<String>,
+    /// Path to the DepotDownloader executable, looked up on PATH when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depotdownloader: Option<String>,
+    /// Script the mock downloader follows, see `downloader::MockScript`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_script: Option<String>,
+
+    /// `page` or `api`, see `workshop::MetadataSource`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_source: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub community_url: Option<String>
+}
+
+fn default_batch_size() -> usize {
+    DEFAULT_BATCH_SIZE
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_RETRIES
+}
+
+fn default_retry_delay() -> u64 {
+    DEFAULT_RETRY_DELAY
+}
+
+fn default_jobs() -> usize {
+    DEFAULT_JOBS
+}
+
+fn default_watchdog() -> u64 {
+    DEFAULT_WATCHDOG
 }
 
 impl ConfigProperties {
-    #[warn(dead_code)]
     pub fn new() -> Self {
-        ConfigProperties { aliases: HashMap::new() }
+        ConfigProperties {
+            aliases: HashMap::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            retries: DEFAULT_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            jobs: DEFAULT_JOBS,
+            watchdog: DEFAULT_WATCHDOG,
+            accounts: HashMap::new(),
+            alias_accounts: HashMap::new(),
+            alias_install_dirs: HashMap::new(),
+            steamcmd: None,
+            downloader: None,
+            depotdownloader: None,
+            mock_script: None,
+            metadata_source: None,
+            api_key: None,
+            api_url: None,
+            community_url: None
+        }
     }
 
     pub fn get_app_id_by_name(&self, name: String) -> Option<String> {
@@ -246,8 +1141,53 @@ impl ConfigProperties {
         None
     }
 
-    pub fn set_alias(&mut self, app_id: String, title_el: ElementRef) {
-        let name: String = underscorize(title_el.text().map(|x| x).collect());
+    pub fn get_account_by_app_id(&self, app_id: String) -> Option<String> {
+        let alias = self.get_name_by_app_id(app_id)?;
+
+        self.alias_accounts.get(&alias).cloned()
+    }
+
+    pub fn get_install_dir_by_app_id(&self, app_id: String) -> Option<String> {
+        let alias = self.get_name_by_app_id(app_id)?;
+
+        self.alias_install_dirs.get(&alias).cloned()
+    }
+
+    pub fn set_account(&mut self, name: String, username: String) {
+        match self.accounts.insert(name.clone(), username) {
+            None => log(
+                LogLevel::INFO, 
+                format!("Added account '{}'", name)
+            ),
+            _ => log(
+                LogLevel::INFO, 
+                format!("Updated account '{}'", name)
+            ),
+        }
+    }
+
+    pub fn remove_account(&mut self, name: String) -> bool {
+        match self.accounts.remove(&name) {
+            Some(_) => {
+                self.alias_accounts.retain(|_, account| *account != name);
+                log(
+                    LogLevel::WARN, 
+                    format!("Removed account '{}'", name)
+                );
+                true
+            },
+            None => {
+                log(
+                    LogLevel::ERR, 
+                    format!("No account named '{}'", name)
+                );
+                false
+            }
+        }
+    }
+
+    pub fn set_alias(&mut self, app_id: String, title: String) {
+        let name: String = underscorize(title);
 
         match self.aliases.insert(name.clone(), app_id.trim().to_string()) {
             None => log(
@@ -273,18 +1213,91 @@ impl ConfigProperties {
         }
     }
 
-    pub fn remove_alias(&mut self, name: String) {
+    pub fn remove_alias(&mut self, name: String) -> bool {
         let name: String = underscorize(name);
 
         match self.aliases.remove(&name) {
-            Some(_) => log(
-                LogLevel::WARN, 
-                format!("Removed alias for '{}'", name)
-            ),
-            None => log(
-                LogLevel::ERR, 
-                format!("No alias found for '{}'", name)
-            )
+            Some(_) => {
+                // an alias added later under the same name starts without them
+                self.alias_accounts.remove(&name);
+                self.alias_install_dirs.remove(&name);
+                log(
+                    LogLevel::WARN, 
+                    format!("Removed alias for '{}'", name)
+                );
+                true
+            },
+            None => {
+                log(
+                    LogLevel::ERR, 
+                    format!("No alias found for '{}'", name)
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workshop::RequiredItem;
+
+    /// A manager with the given items in its cache, so nothing is fetched or written
+    fn cached(items: &[(u64, Vec<u64>)]) -> Manager {
+        let mut manager = Manager { queue: Queue::default(), config: Config::new(), cache: Cache::default() };
+
+        for (id, required) in items.iter() {
+            manager.cache.insert(ItemDetails {
+                id: ItemId(*id),
+                app_id: Some(String::from("294100")),
+                title: format!("Item {}", id),
+                author: None,
+                size: None,
+                posted: None,
+                updated: None,
+                tags: vec![],
+                rating: None,
+                subscribers: None,
+                description: String::new(),
+                required_items: required.iter().map(|x| RequiredItem { id: ItemId(*x), title: format!("Item {}", x) }).collect(),
+                required_dlc: vec![],
+                fetched_at: 0,
+            });
         }
+
+        manager
     }
-}
\ No newline at end of file
+
+    /// `(id, required_by)` of what resolving `roots` finds
+    fn resolve(manager: &mut Manager, roots: &[u64]) -> Vec<(u64, u64)> {
+        let roots: Vec<ItemId> = roots.iter().map(|x| ItemId(*x)).collect();
+
+        manager.resolve_dependencies("294100", &roots, MetadataSource::Page).dependencies.iter()
+            .map(|x| (x.id.0, x.required_by.0))
+            .collect()
+    }
+
+    #[test]
+    fn dependency_cycles_are_not_followed() {
+        let mut manager = cached(&[(1, vec![2]), (2, vec![1])]);
+        assert_eq!(resolve(&mut manager, &[1]), [(2, 1)]);
+
+        let mut manager = cached(&[(1, vec![2]), (2, vec![3]), (3, vec![1, 4]), (4, vec![])]);
+        assert_eq!(resolve(&mut manager, &[1]), [(2, 1), (3, 2), (4, 3)]);
+    }
+
+    #[test]
+    fn shared_dependencies_are_added_once() {
+        let mut manager = cached(&[(1, vec![3]), (2, vec![3, 4]), (3, vec![4]), (4, vec![])]);
+        assert_eq!(resolve(&mut manager, &[1, 2]), [(3, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn roots_required_by_other_roots() {
+        // 2 is already being added, but what it requires still is
+        let mut manager = cached(&[(1, vec![2]), (2, vec![3]), (3, vec![])]);
+        assert_eq!(resolve(&mut manager, &[1, 2]), [(3, 2)]);
+        assert_eq!(resolve(&mut manager, &[2, 1]), [(3, 2)]);
+    }
+}
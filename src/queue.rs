@@ -0,0 +1,329 @@
+use std::{collections::HashMap, fmt, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{log, LogLevel};
+
+/// Published file id of a workshop item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemId(pub u64);
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ItemId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().parse::<u64>().map(ItemId)
+    }
+}
+
+/// Items to add with their titles, when known
+pub type TitledItems = Vec<(ItemId, Option<String>)>;
+
+/// Where a queued item came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    Search { query: String },
+    Convert { file: String },
+    Collection { id: ItemId },
+    Import { file: String },
+    /// Required by another item, added with `--with-deps`
+    Dependency { of: ItemId },
+    Manual,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Search { query } => write!(f, "search \"{}\"", query),
+            Source::Convert { file } => write!(f, "convert {}", file),
+            Source::Collection { id } => write!(f, "collection {}", id),
+            Source::Import { file } => write!(f, "import {}", file),
+            Source::Dependency { of } => write!(f, "dependency of {}", of),
+            Source::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Status {
+    Pending,
+    Downloading,
+    Downloaded,
+    Failed { reason: String },
+    Cancelled,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Downloading => "downloading",
+            Status::Downloaded => "downloaded",
+            Status::Failed { .. } => "failed",
+            Status::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Failed { reason } => write!(f, "failed ({})", reason),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// Which statuses a command acts on, parsed from e.g. `pending,failed` or `all`
+#[derive(Debug, Clone)]
+pub struct StatusFilter(Vec<&'static str>);
+
+impl StatusFilter {
+    pub const STATUSES: [&'static str; 5] = ["pending", "downloading", "downloaded", "failed", "cancelled"];
+
+    pub fn matches(&self, status: &Status) -> bool {
+        self.0.contains(&status.name())
+    }
+}
+
+impl FromStr for StatusFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(StatusFilter(StatusFilter::STATUSES.to_vec()));
+        }
+
+        let mut statuses = vec![];
+        for name in s.split(',').map(|x| x.trim()) {
+            match StatusFilter::STATUSES.iter().find(|status| **status == name) {
+                Some(status) => statuses.push(*status),
+                None => return Err(format!(
+                    "'{}' is not a valid status, expected 'all' or any of: {}",
+                    name, StatusFilter::STATUSES.join(", ")
+                ))
+            }
+        }
+
+        Ok(StatusFilter(statuses))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub app_id: String,
+    pub app_name: String,
+    pub item_id: ItemId,
+    pub title: Option<String>,
+    pub source: Source,
+    /// Unix timestamp in seconds
+    pub added_at: u64,
+    pub status: Status,
+}
+
+impl QueueEntry {
+    pub fn new(app_id: String, app_name: String, item_id: ItemId, title: Option<String>, source: Source) -> Self {
+        let added_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs());
+
+        QueueEntry { app_id, app_name, item_id, title, source, added_at, status: Status::Pending }
+    }
+
+    pub fn display_title(&self) -> String {
+        match &self.title {
+            Some(title) => format!("{} [{}]", title, self.item_id),
+            None => format!("[{}]", self.item_id)
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Queue {
+    pub entries: Vec<QueueEntry>
+}
+
+/// Queue files from before items had a status, `app_id -> (app_name, ids)`
+#[derive(Deserialize)]
+struct LegacyQueue {
+    workshop: HashMap<String, (String, Vec<u64>)>,
+    #[serde(default)]
+    titles: HashMap<u64, String>
+}
+
+impl Queue {
+    /// Restores the items that were added in a previous session
+    pub fn load(path: &str) -> Self {
+        let text_data = match std::fs::read_to_string(path) {
+            Ok(text_data) => text_data,
+            Err(_) => return Queue::default()
+        };
+
+        let mut queue = match serde_json::from_str::<Queue>(&text_data) {
+            Ok(queue) => queue,
+            Err(_) => match serde_json::from_str::<LegacyQueue>(&text_data) {
+                Ok(legacy) => Queue::from_legacy(legacy),
+                Err(_) => {
+                    log(
+                        LogLevel::WARN,
+                        format!("Invalid json format for '{}', starting with an empty queue", path)
+                    );
+                    return Queue::default();
+                }
+            }
+        };
+
+        // items still downloading when the last session died, they'd never be picked up again
+        let interrupted = queue.entries.iter_mut()
+            .filter(|x| x.status == Status::Downloading)
+            .map(|x| x.status = Status::Pending)
+            .count();
+        if interrupted > 0 {
+            log(
+                LogLevel::WARN,
+                format!("{} items were interrupted while downloading, they are pending again", interrupted)
+            );
+        }
+
+        let mut pending: Vec<(&String, usize)> = vec![];
+        for entry in queue.entries.iter().filter(|x| x.status == Status::Pending) {
+            match pending.iter_mut().find(|(name, _)| **name == entry.app_name) {
+                Some((_, count)) => *count += 1,
+                None => pending.push((&entry.app_name, 1))
+            }
+        }
+
+        for (app_name, count) in pending {
+            log(
+                LogLevel::INFO,
+                format!("Recovered {} pending items for '{}'", count, app_name)
+            );
+        }
+
+        queue
+    }
+
+    fn from_legacy(legacy: LegacyQueue) -> Self {
+        let mut queue = Queue::default();
+
+        for (app_id, (app_name, item_ids)) in legacy.workshop.into_iter() {
+            for id in item_ids {
+                queue.add(QueueEntry::new(
+                    app_id.clone(), app_name.clone(), ItemId(id), legacy.titles.get(&id).cloned(), Source::Manual
+                ));
+            }
+        }
+
+        queue
+    }
+
+    pub fn save(&self, path: &str) {
+        let str_data = serde_json::to_string(&self).unwrap();
+
+        if std::fs::write(path, str_data).is_err() {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't write the queue to '{}'", path)
+            );
+        }
+    }
+
+    /// Adds an entry unless the same item of the same app is already queued
+    pub fn add(&mut self, entry: QueueEntry) -> bool {
+        if self.contains(&entry.app_id, entry.item_id) {
+            return false;
+        }
+
+        self.entries.push(entry);
+        true
+    }
+
+    pub fn contains(&self, app_id: &str, item_id: ItemId) -> bool {
+        self.entries.iter().any(|x| x.app_id == app_id && x.item_id == item_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Positions of the entries grouped by app name, keeping the order they
+    /// were added in within an app. This is the order items are listed,
+    /// exported and downloaded in.
+    pub fn ordered(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by(|a, b| self.entries[*a].app_name.cmp(&self.entries[*b].app_name));
+
+        order
+    }
+
+    /// Entries matching `filter`, in listing order
+    pub fn select(&self, filter: &StatusFilter) -> Vec<&QueueEntry> {
+        self.ordered().into_iter()
+            .map(|i| &self.entries[i])
+            .filter(|x| filter.matches(&x.status))
+            .collect()
+    }
+
+    /// Looks an entry up by its published file id, or by its index in the listing
+    pub fn find(&self, key: &str) -> Option<usize> {
+        let number = key.parse::<u64>().ok()?;
+
+        self.entries.iter().position(|x| x.item_id == ItemId(number))
+            .or_else(|| self.ordered().get(number as usize).copied())
+    }
+
+    pub fn remove(&mut self, position: usize) -> QueueEntry {
+        self.entries.remove(position)
+    }
+
+    /// Removes every entry of an app, returns how many were removed
+    pub fn clear_app(&mut self, app_id: &str) -> usize {
+        let len = self.entries.len();
+        self.entries.retain(|x| x.app_id != app_id);
+
+        len - self.entries.len()
+    }
+
+    /// Moves an entry to `position` among the entries of its app
+    pub fn move_within_app(&mut self, from: usize, position: usize) -> usize {
+        let entry = self.entries.remove(from);
+        let app_positions: Vec<usize> = (0..self.entries.len())
+            .filter(|i| self.entries[*i].app_id == entry.app_id)
+            .collect();
+
+        let position = position.min(app_positions.len());
+        let target = match app_positions.get(position) {
+            Some(i) => *i,
+            None => app_positions.last().map_or(self.entries.len(), |i| i + 1)
+        };
+
+        self.entries.insert(target, entry);
+        position
+    }
+
+    /// Sets the title of the entries of an item that don't have one yet
+    pub fn fill_title(&mut self, item_id: ItemId, title: &str) -> usize {
+        let mut filled = 0;
+        for entry in self.entries.iter_mut().filter(|x| x.item_id == item_id && x.title.is_none()) {
+            entry.title = Some(title.to_string());
+            filled += 1;
+        }
+
+        filled
+    }
+
+    pub fn set_status(&mut self, app_id: &str, item_id: ItemId, status: Status) {
+        if let Some(entry) = self.entries.iter_mut().find(|x| x.app_id == app_id && x.item_id == item_id) {
+            entry.status = status;
+        }
+    }
+}
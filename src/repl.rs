@@ -0,0 +1,170 @@
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::commands::{self, CommandSpec, OptionSpec, ValueKind};
+use crate::manager::Manager;
+use crate::utils::{log, LogLevel};
+use crate::VERSION;
+
+const HISTORY_FILE: &str = "history.txt";
+
+/// Reads commands with line editing and history until `exit` or Ctrl-D
+pub fn run(manager: &mut Manager) {
+    let mut editor = match Editor::<RswHelper>::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't start the prompt: {}", err)
+            );
+            return;
+        }
+    };
+
+    editor.set_helper(Some(RswHelper::new()));
+    // there's no history on the first run
+    let _ = editor.load_history(HISTORY_FILE);
+
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.aliases = manager.config.get_props_ref()
+                .map(|props| props.aliases.keys().cloned().collect())
+                .unwrap_or_default();
+            helper.accounts = manager.config.get_props_ref()
+                .map(|props| props.accounts.keys().cloned().collect())
+                .unwrap_or_default();
+        }
+
+        match editor.readline(&format!("RSWC (v{:?})> ", VERSION)) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor.add_history_entry(line.as_str());
+                    // saved right away since `exit` doesn't come back here
+                    let _ = editor.save_history(HISTORY_FILE);
+                }
+
+                // errors are logged where they happen, the kind only matters for one-shot runs
+                let _ = commands::execute_line(manager, line);
+            },
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => {
+                let _ = commands::execute(manager, vec![String::from("exit")]);
+            },
+            Err(err) => {
+                log(
+                    LogLevel::ERR,
+                    format!("Couldn't read input: {}", err)
+                );
+                return;
+            }
+        }
+    }
+}
+
+pub struct RswHelper {
+    /// Alias names from the config, refreshed before every prompt
+    pub aliases: Vec<String>,
+    /// Account names from the config, refreshed before every prompt
+    pub accounts: Vec<String>,
+    files: FilenameCompleter
+}
+
+impl RswHelper {
+    pub fn new() -> Self {
+        RswHelper { aliases: vec![], accounts: vec![], files: FilenameCompleter::new() }
+    }
+
+    fn find_option(spec: &'static CommandSpec, word: &str) -> Option<&'static OptionSpec> {
+        spec.options.iter().find(|option| {
+            option.name == word || (word.len() == 2 && option.short == word.chars().nth(1))
+        })
+    }
+}
+
+impl Completer for RswHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let spec = match words.first() {
+            Some(name) => match commands::find(name) {
+                Some(spec) => spec,
+                None => return Ok((start, vec![]))
+            },
+            None => {
+                let names = commands::REGISTRY.iter()
+                    .flat_map(|spec| std::iter::once(&spec.name).chain(spec.aliases.iter()));
+
+                return Ok((start, candidates(word, names.copied())));
+            }
+        };
+
+        if word.starts_with('-') {
+            return Ok((start, candidates(word, spec.options.iter().map(|option| option.name))));
+        }
+
+        // the word is either the value of the option before it or a positional arg
+        let mut position = 0;
+        let mut pending_option: Option<&'static OptionSpec> = None;
+        for word in words[1..].iter() {
+            if pending_option.take().is_some() {
+                continue;
+            }
+
+            if word.starts_with('-') {
+                pending_option = RswHelper::find_option(spec, word)
+                    .filter(|option| option.kind != ValueKind::Flag);
+                continue;
+            }
+
+            position += 1;
+        }
+
+        let (kind, choices) = match pending_option {
+            Some(option) => (option.kind, &[][..]),
+            None => match spec.args.get(position) {
+                Some(arg) => (arg.kind, arg.choices),
+                None => return Ok((start, vec![]))
+            }
+        };
+
+        if !choices.is_empty() {
+            return Ok((start, candidates(word, choices.iter().copied())));
+        }
+
+        match kind {
+            ValueKind::Alias => Ok((start, candidates(word, self.aliases.iter().map(|x| x.as_str())))),
+            ValueKind::Account => Ok((start, candidates(word, self.accounts.iter().map(|x| x.as_str())))),
+            ValueKind::Path => self.files.complete(line, pos, ctx),
+            _ => Ok((start, vec![]))
+        }
+    }
+}
+
+impl Hinter for RswHelper {
+    type Hint = String;
+}
+
+impl Highlighter for RswHelper {}
+
+impl Validator for RswHelper {}
+
+impl Helper for RswHelper {}
+
+fn candidates<'a>(word: &str, options: impl Iterator<Item = &'a str>) -> Vec<Pair> {
+    let mut pairs: Vec<Pair> = options
+        .filter(|option| option.starts_with(word))
+        .map(|option| Pair { display: option.to_string(), replacement: format!("{} ", option) })
+        .collect();
+
+    pairs.sort_by(|a, b| a.display.cmp(&b.display));
+    pairs
+}
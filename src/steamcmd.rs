@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::queue::{ItemId, QueueEntry};
+use crate::utils;
+
+/// An item to download, with its title for reporting
+#[derive(Debug, Clone)]
+pub struct DownloadItem {
+    pub app_id: String,
+    pub item_id: ItemId,
+    pub title: Option<String>,
+    /// Steam username to log in with, anonymous when not set
+    pub account: Option<String>,
+    /// Passed to `+force_install_dir`, steamcmd's own dir when not set
+    pub install_dir: Option<PathBuf>,
+}
+
+impl From<&QueueEntry> for DownloadItem {
+    fn from(entry: &QueueEntry) -> Self {
+        DownloadItem {
+            app_id: entry.app_id.clone(), item_id: entry.item_id, title: entry.title.clone(), account: None,
+            install_dir: None
+        }
+    }
+}
+
+impl fmt::Display for DownloadItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.title {
+            Some(title) => write!(f, "{} [{}]", title, self.item_id),
+            None => write!(f, "[{}]", self.item_id)
+        }
+    }
+}
+
+/// What steamcmd reported for a single `workshop_download_item`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemResult {
+    Downloaded { path: String, bytes: Option<u64> },
+    Failed { reason: String },
+    /// The download was cancelled before steamcmd got to the item
+    Cancelled,
+}
+
+/// Overrides the steamcmd path from the config
+pub const ENV_VAR: &str = "RSW_STEAMCMD";
+
+/// Executable names looked up on `PATH`
+const NAMES: [&str; 3] = ["steamcmd", "steamcmd.sh", "steamcmd.exe"];
+
+/// Usual install locations, relative to the home dir
+const HOME_LOCATIONS: [&str; 5] = [
+    "Steam/steamcmd",
+    "Steam/steamcmd.sh",
+    "steamcmd/steamcmd.sh",
+    "steamcmd/steamcmd.exe",
+    ".steam/steamcmd/steamcmd.sh",
+];
+
+/// Usual install locations outside the home dir
+const SYSTEM_LOCATIONS: [&str; 3] = [
+    "/usr/games/steamcmd",
+    "/usr/lib/games/steam/steamcmd.sh",
+    "C:/steamcmd/steamcmd.exe",
+];
+
+/// Finds steamcmd, in order: `explicit` (`--steamcmd`), the `RSW_STEAMCMD`
+/// environment variable, `configured` (`steamcmd` in the config), `PATH`
+/// and the usual install locations. A path that is given but doesn't
+/// exist is an error rather than falling through to the next one.
+pub fn locate(explicit: Option<&str>, configured: Option<&str>) -> Result<PathBuf, String> {
+    let env_value = env::var(ENV_VAR).ok().filter(|x| !x.trim().is_empty());
+    let given = [
+        (explicit.map(|x| x.to_string()), "--steamcmd"),
+        (env_value, ENV_VAR),
+        (configured.map(|x| x.to_string()), "'steamcmd' in config.json"),
+    ];
+
+    for (path, origin) in given {
+        if let Some(path) = path {
+            return match Path::new(&path).is_file() {
+                true => Ok(PathBuf::from(path)),
+                false => Err(format!("steamcmd not found at '{}' (from {})", path, origin))
+            };
+        }
+    }
+
+    detect().ok_or_else(|| format!(
+        "steamcmd not found on PATH or in the usual install locations, \
+        set 'steamcmd' in config.json, {} or pass --steamcmd",
+        ENV_VAR
+    ))
+}
+
+fn detect() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from);
+    let in_home = home.into_iter()
+        .flat_map(|home| HOME_LOCATIONS.iter().map(move |location| home.join(location)));
+
+    let system = SYSTEM_LOCATIONS.iter().map(PathBuf::from);
+
+    utils::find_on_path(&NAMES).or_else(|| in_home.chain(system).find(|path| path.is_file()))
+}
+
+/// Prefix of the environment variables holding account passwords, e.g. `RSW_PASSWORD_MAIN`
+pub const PASSWORD_ENV_PREFIX: &str = "RSW_PASSWORD_";
+
+/// The environment variable holding the password of the account `name`
+pub fn password_env_var(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+
+    format!("{}{}", PASSWORD_ENV_PREFIX, name)
+}
+
+/// The commands that log in as `account`, e.g. `["login", "anonymous"]`. The
+/// password is left out if there is none, so steamcmd uses its cached credentials.
+pub fn login_command(account: Option<&str>, passwords: &HashMap<String, String>) -> Vec<String> {
+    match account {
+        Some(username) => match passwords.get(username).filter(|x| !x.is_empty()) {
+            Some(password) => vec![String::from("login"), username.to_string(), password.clone()],
+            None => vec![String::from("login"), username.to_string()]
+        },
+        None => vec![String::from("login"), String::from("anonymous")]
+    }
+}
+
+/// The args steamcmd is started with to download `items`, logging in again
+/// whenever the account changes between items
+pub fn download_args(items: &[DownloadItem], passwords: &HashMap<String, String>) -> Vec<String> {
+    let mut args: Vec<String> = vec![];
+    let mut current: Option<Option<&str>> = None;
+
+    for item in items.iter() {
+        if current != Some(item.account.as_deref()) {
+            let mut login = login_command(item.account.as_deref(), passwords);
+            login[0] = format!("+{}", login[0]);
+
+            args.extend(login);
+            current = Some(item.account.as_deref());
+        }
+
+        args.push(String::from("+workshop_download_item"));
+        args.push(item.app_id.clone());
+        args.push(item.item_id.to_string());
+    }
+
+    if args.is_empty() {
+        args.extend([String::from("+login"), String::from("anonymous")]);
+    }
+
+    args.push(String::from("+quit"));
+    args
+}
+
+/// Whether steamcmd (or DepotDownloader) is waiting for input, e.g.
+/// `Steam Guard code:` or `Two-factor code:`. These don't end with a new line.
+pub fn is_prompt(line: &str) -> bool {
+    const PROMPTS: [&str; 4] = ["Steam Guard", "Two-factor", "auth code", "password"];
+    let line = line.trim();
+
+    line.ends_with(':') && PROMPTS.iter().any(|prompt| line.contains(prompt))
+}
+
+/// Reads a single line of steamcmd output, e.g.
+///
+/// - `Success. Downloaded item 123 to "/steamapps/workshop/content/294100/123" (4096 bytes)`
+/// - `ERROR! Download item 123 failed (Timeout).`
+pub fn parse_line(line: &str) -> Option<(ItemId, ItemResult)> {
+    let line = line.trim();
+
+    if let Some(rest) = line.strip_prefix("Success. Downloaded item ") {
+        let (id, rest) = rest.split_once(' ')?;
+        let id = id.parse::<ItemId>().ok()?;
+        let rest = rest.strip_prefix("to ")?;
+
+        // the path is quoted and may contain spaces, the size follows it
+        let (path, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"')?,
+            None => rest.split_once(' ').unwrap_or((rest, ""))
+        };
+        let bytes = rest.trim()
+            .strip_prefix('(')
+            .and_then(|x| x.split_whitespace().next())
+            .and_then(|x| x.parse::<u64>().ok());
+
+        return Some((id, ItemResult::Downloaded { path: path.to_string(), bytes }));
+    }
+
+    if let Some(rest) = line.strip_prefix("ERROR! Download item ") {
+        let (id, rest) = rest.split_once(' ')?;
+        let id = id.parse::<ItemId>().ok()?;
+        let reason = rest.strip_prefix("failed")?
+            .trim()
+            .trim_end_matches('.')
+            .trim_start_matches('(')
+            .trim_end_matches(')');
+
+        let reason = match reason.is_empty() {
+            true => String::from("unknown error"),
+            false => reason.to_string()
+        };
+
+        return Some((id, ItemResult::Failed { reason }));
+    }
+
+    None
+}
+
+/// What steamcmd is doing while it downloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// `Downloading item 123 ...`
+    Item(ItemId),
+    /// `... progress: 45.23 (1234 / 2730)`, as bytes done and total
+    Bytes { done: u64, total: u64 },
+}
+
+pub fn parse_progress(line: &str) -> Option<Progress> {
+    let line = line.trim();
+
+    if let Some(rest) = line.strip_prefix("Downloading item ") {
+        let id = rest.split_whitespace().next()?.parse::<ItemId>().ok()?;
+
+        return Some(Progress::Item(id));
+    }
+
+    let (_, rest) = line.split_once("progress: ")?;
+    let (_, rest) = rest.split_once('(')?;
+    let (done, rest) = rest.split_once('/')?;
+    let total = rest.trim_start().split(|c: char| !c.is_ascii_digit()).next()?;
+
+    Some(Progress::Bytes { done: done.trim().parse().ok()?, total: total.parse().ok()? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downloaded(id: u64, path: &str, bytes: Option<u64>) -> Option<(ItemId, ItemResult)> {
+        Some((ItemId(id), ItemResult::Downloaded { path: path.to_string(), bytes }))
+    }
+
+    fn failed(id: u64, reason: &str) -> Option<(ItemId, ItemResult)> {
+        Some((ItemId(id), ItemResult::Failed { reason: reason.to_string() }))
+    }
+
+    #[test]
+    fn parse_results() {
+        let cases = [
+            (
+                r#"Success. Downloaded item 123 to "/steamapps/workshop/content/294100/123" (4096 bytes)"#,
+                downloaded(123, "/steamapps/workshop/content/294100/123", Some(4096))
+            ),
+            (
+                r#"Success. Downloaded item 456 to "C:\Program Files\steamcmd\steamapps\workshop\content\294100\456" (12 bytes)"#,
+                downloaded(456, r"C:\Program Files\steamcmd\steamapps\workshop\content\294100\456", Some(12))
+            ),
+            (
+                r#"Success. Downloaded item 789 to "/srv/my mods/789""#,
+                downloaded(789, "/srv/my mods/789", None)
+            ),
+            (
+                "Success. Downloaded item 789 to /srv/mods/789",
+                downloaded(789, "/srv/mods/789", None)
+            ),
+            (
+                "  Success. Downloaded item 1 to \"/a\" (0 bytes)\r",
+                downloaded(1, "/a", Some(0))
+            ),
+            ("ERROR! Download item 123 failed (Timeout).", failed(123, "Timeout")),
+            ("ERROR! Download item 123 failed (Access Denied).", failed(123, "Access Denied")),
+            ("ERROR! Download item 123 failed.", failed(123, "unknown error")),
+            ("Downloading item 123 ...", None),
+            ("Logging in user 'anonymous' to Steam Public...OK", None),
+            ("Success. Downloaded item abc to \"/a\"", None),
+            ("", None),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(parse_line(line), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_progress_lines() {
+        let cases = [
+            ("Downloading item 123 ...", Some(Progress::Item(ItemId(123)))),
+            (
+                " Update state (0x61) downloading, progress: 45.23 (1234 / 2730)",
+                Some(Progress::Bytes { done: 1234, total: 2730 })
+            ),
+            ("progress: 100.00 (2730 / 2730)\r", Some(Progress::Bytes { done: 2730, total: 2730 })),
+            ("Success. Downloaded item 123 to \"/a\" (4096 bytes)", None),
+            ("progress: 12.00", None),
+            ("Loading Steam API...OK", None),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(parse_progress(line), expected, "{}", line);
+        }
+    }
+}
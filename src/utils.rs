@@ -24,4 +24,90 @@ pub fn log(level: LogLevel, message: String) {
         LogLevel::EXIT => println!("[EXIT] {}", message),
 
     }
-}
\ No newline at end of file
+}
+
+/// Why a command failed. The message has already been logged by the time
+/// one of these is returned, so callers only need it to pick an exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Usage,
+    Io,
+    Network,
+    MissingAlias,
+    Steamcmd,
+    Cancelled,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Usage => 2,
+            ErrorKind::Io => 3,
+            ErrorKind::Network => 4,
+            ErrorKind::MissingAlias => 5,
+            ErrorKind::Steamcmd => 6,
+            ErrorKind::Cancelled => 130,
+        }
+    }
+}
+
+/// The first of `names` found in a dir on `PATH`
+pub fn find_on_path(names: &[&str]) -> Option<std::path::PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+
+    std::env::split_paths(&paths)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// `command` as it would be typed in a shell, quoting arguments that need it
+pub fn command_line(command: &std::process::Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|x| {
+            let arg = x.to_string_lossy();
+            let plain = |c: char| c.is_alphanumeric() || "-_./:+=,@%".contains(c);
+            match !arg.is_empty() && arg.chars().all(plain) {
+                true => arg.into_owned(),
+                false => format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM` (UTC)
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hours, minutes) = ((secs % 86400) / 3600, (secs % 3600) / 60);
+
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hours, minutes)
+}
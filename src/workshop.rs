@@ -0,0 +1,457 @@
+use std::{collections::HashMap, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::queue::ItemId;
+use crate::utils::{log, LogLevel};
+
+/// Overrides `community_url` from the config, e.g. for a mirror or a server with saved pages
+pub const COMMUNITY_URL_ENV_VAR: &str = "RSW_COMMUNITY_URL";
+
+pub const DEFAULT_COMMUNITY_URL: &str = "https://steamcommunity.com";
+
+/// Where item details, collections and search results are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataSource {
+    /// Scraped from the community pages
+    Page,
+    /// The Steam Web API, see `api::Client`
+    Api,
+}
+
+impl MetadataSource {
+    pub const NAMES: [&'static str; 2] = ["page", "api"];
+}
+
+impl FromStr for MetadataSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "page" => Ok(MetadataSource::Page),
+            "api" => Ok(MetadataSource::Api),
+            _ => Err(format!(
+                "'{}' is not a valid source, expected one of: {}",
+                s, MetadataSource::NAMES.join(", ")
+            ))
+        }
+    }
+}
+
+/// What the page of a workshop item says about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDetails {
+    pub id: ItemId,
+    #[serde(default)]
+    pub app_id: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// As shown on the page, e.g. `12.345 MB`
+    #[serde(default)]
+    pub size: Option<String>,
+    #[serde(default)]
+    pub posted: Option<String>,
+    #[serde(default)]
+    pub updated: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub rating: Option<String>,
+    #[serde(default)]
+    pub subscribers: Option<u64>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required_items: Vec<RequiredItem>,
+    #[serde(default)]
+    pub required_dlc: Vec<RequiredDlc>,
+    /// Unix timestamp in seconds
+    pub fetched_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequiredItem {
+    pub id: ItemId,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequiredDlc {
+    pub app_id: String,
+    pub name: String,
+}
+
+impl ItemDetails {
+    pub fn display_title(&self) -> String {
+        format!("{} [{}]", self.title, self.id)
+    }
+}
+
+/// The item id in `input`, either the id itself or a link to the item page
+pub fn parse_id(input: &str) -> Option<ItemId> {
+    let input = input.trim();
+    if let Ok(id) = input.parse::<ItemId>() {
+        return Some(id);
+    }
+
+    // https://steamcommunity.com/sharedfiles/filedetails/?id=123&searchtext=
+    let query = input.split_once('?')?.1;
+    query.split('&')
+        .find_map(|x| x.strip_prefix("id="))
+        .and_then(|x| x.parse::<ItemId>().ok())
+}
+
+pub fn item_url(base_url: &str, id: ItemId) -> String {
+    format!("{}/sharedfiles/filedetails/?id={}", base_url.trim_end_matches('/'), id)
+}
+
+/// Fetches the page of an item. `Ok(None)` when steam has no item with that id.
+pub fn fetch_item(base_url: &str, id: ItemId) -> Result<Option<ItemDetails>, String> {
+    let text = reqwest::blocking::get(item_url(base_url, id))
+        .and_then(|res| res.text())
+        .map_err(|_| String::from("Couldn't access steam workshop at this time"))?;
+
+    Ok(parse_item_page(id, &Html::parse_document(&text)))
+}
+
+/// Reads an item page, `None` when it is steam's error page
+pub fn parse_item_page(id: ItemId, html: &Html) -> Option<ItemDetails> {
+    let title = first_text(html.root_element(), ".workshopItemTitle")?;
+
+    // labels on the left, values on the right: File Size, Posted, Updated
+    let labels: Vec<String> = html.select(&selector(".detailsStatsContainerLeft .detailsStatLeft"))
+        .map(text)
+        .collect();
+    let values: Vec<String> = html.select(&selector(".detailsStatsContainerRight .detailsStatRight"))
+        .map(text)
+        .collect();
+    let stat = |label: &str| labels.iter()
+        .position(|x| x.eq_ignore_ascii_case(label))
+        .and_then(|i| values.get(i).cloned());
+
+    let tags: Vec<String> = html.select(&selector(".workshopTags a")).map(text).collect();
+
+    // the stars are only an image, e.g. `4-star_large.png`
+    let stars = html.select(&selector(".fileRatingDetails img")).next()
+        .and_then(|x| x.value().attr("src"))
+        .and_then(|src| src.rsplit('/').next())
+        .and_then(|file| file.split('-').next())
+        .and_then(|x| x.parse::<u8>().ok());
+    let ratings = first_text(html.root_element(), ".numRatings");
+    let rating = match (stars, ratings) {
+        (Some(stars), Some(ratings)) => Some(format!("{}/5 ({})", stars, ratings)),
+        (Some(stars), None) => Some(format!("{}/5", stars)),
+        (None, ratings) => ratings
+    };
+
+    let subscribers = html.select(&selector(".stats_table tr"))
+        .find(|row| text(*row).contains("Current Subscribers"))
+        .and_then(|row| row.select(&selector("td")).next())
+        .and_then(|cell| text(cell).replace(',', "").parse::<u64>().ok());
+
+    let required_items = html.select(&selector("#RequiredItems a"))
+        .filter_map(|link| {
+            let id = parse_id(link.value().attr("href")?)?;
+            Some(RequiredItem { id, title: text(link) })
+        })
+        .collect();
+
+    let required_dlc = html.select(&selector(".requiredDLCItem a"))
+        .filter_map(|link| {
+            let app_id = app_id_from_link(link.value().attr("href")?)?;
+            Some(RequiredDlc { app_id, name: text(link) })
+        })
+        .collect();
+
+    Some(ItemDetails {
+        id,
+        app_id: app_id(html),
+        title,
+        author: html.select(&selector(".friendBlockContent")).next()
+            .and_then(|x| x.text().map(|x| x.trim()).find(|x| !x.is_empty()))
+            .map(|x| x.to_string()),
+        size: stat("File Size"),
+        posted: stat("Posted"),
+        updated: stat("Updated"),
+        tags,
+        rating,
+        subscribers,
+        description: first_text(html.root_element(), ".workshopItemDescription").unwrap_or_default(),
+        required_items,
+        required_dlc,
+        fetched_at: now(),
+    })
+}
+
+/// What the page of a collection lists
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub id: ItemId,
+    pub app_id: Option<String>,
+    pub title: String,
+    /// `(id, title)` of the items in it, in the order of the page
+    pub items: Vec<(ItemId, String)>,
+    /// `(id, title)` of the collections linked from it
+    pub collections: Vec<(ItemId, String)>,
+}
+
+/// Fetches the page of a collection. `Ok(None)` when steam has no collection with that id.
+pub fn fetch_collection(base_url: &str, id: ItemId) -> Result<Option<Collection>, String> {
+    let text = reqwest::blocking::get(item_url(base_url, id))
+        .and_then(|res| res.text())
+        .map_err(|_| String::from("Couldn't access steam workshop at this time"))?;
+
+    Ok(parse_collection_page(id, &Html::parse_document(&text)))
+}
+
+/// Reads a collection page, `None` when it isn't one (an item page or steam's error page)
+pub fn parse_collection_page(id: ItemId, html: &Html) -> Option<Collection> {
+    let title = first_text(html.root_element(), ".workshopItemTitle")?;
+
+    // <div class="collectionItem" id="sharedfile_123">
+    let items: Vec<(ItemId, String)> = html.select(&selector(".collectionItem"))
+        .filter_map(|item| {
+            let id = item.value().attr("id")
+                .and_then(|x| x.strip_prefix("sharedfile_"))
+                .and_then(|x| x.parse::<ItemId>().ok())
+                .or_else(|| item.select(&selector("a")).find_map(|x| parse_id(x.value().attr("href")?)))?;
+
+            Some((id, first_text(item, ".workshopItemTitle").unwrap_or_default()))
+        })
+        .collect();
+
+    // linked collections are plain workshop items outside of the `.collectionItem`s
+    let mut collections: Vec<(ItemId, String)> = vec![];
+    for link in html.select(&selector(".collectionChildren .workshopItem a, .workshopItemCollectionContainer a")) {
+        let child = match link.value().attr("href").and_then(parse_id) {
+            Some(child) => child,
+            None => continue
+        };
+        if child == id || items.iter().any(|(x, _)| *x == child) || collections.iter().any(|(x, _)| *x == child) {
+            continue;
+        }
+
+        let container = link.ancestors().filter_map(ElementRef::wrap)
+            .find(|x| x.value().classes().any(|class| class == "workshopItem" || class == "workshopItemCollectionContainer"));
+        let title = container.and_then(|x| first_text(x, ".workshopItemTitle"))
+            .unwrap_or_else(|| text(link));
+        collections.push((child, title));
+    }
+
+    if items.is_empty() && collections.is_empty() && html.select(&selector(".collectionChildren")).next().is_none() {
+        return None;
+    }
+
+    Some(Collection { id, app_id: app_id(html), title, items, collections })
+}
+
+/// What a page of the workshop's search results lists
+#[derive(Debug, Clone)]
+pub struct BrowsePage {
+    /// The page's `<title>`, the alias of the app is named after it
+    pub title: Option<String>,
+    /// `(id, title)` of the items on it, in the order of the page
+    pub items: Vec<(ItemId, String)>,
+}
+
+/// Reads a page of search results, `None` when steam has no workshop for the app
+pub fn parse_browse_page(html: &Html) -> Option<BrowsePage> {
+    html.select(&selector(".apphub_HomeHeaderContent")).next()?;
+
+    // <div class="workshopItem"><a class="ugc" data-publishedfileid="123">
+    let items: Vec<(ItemId, String)> = html.select(&selector(".workshopItem"))
+        .filter_map(|item| {
+            let id = item.select(&selector(".ugc"))
+                .find_map(|x| x.value().attr("data-publishedfileid")?.parse::<ItemId>().ok())?;
+
+            Some((id, first_text(item, ".workshopItemTitle").unwrap_or_default()))
+        })
+        .collect();
+
+    Some(BrowsePage { title: first_text(html.root_element(), "title"), items })
+}
+
+/// The app a workshop page belongs to, from the link back to the app's workshop
+pub fn app_id(html: &Html) -> Option<String> {
+    html.select(&selector(".breadcrumbs a, .apphub_OtherSiteInfo a"))
+        .find_map(|x| app_id_from_link(x.value().attr("href")?))
+        .or_else(|| html.select(&selector("[data-appid]")).find_map(|x| x.value().attr("data-appid").map(String::from)))
+}
+
+/// `https://steamcommunity.com/app/294100/workshop/` or `https://store.steampowered.com/app/294100`
+fn app_id_from_link(href: &str) -> Option<String> {
+    let rest = href.split("/app/").nth(1)?;
+    let app_id: String = rest.chars().take_while(|x| x.is_ascii_digit()).collect();
+
+    match app_id.is_empty() {
+        true => None,
+        false => Some(app_id)
+    }
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).unwrap()
+}
+
+/// The text of an element with its whitespace collapsed, `<br>`s become spaces
+fn text(element: ElementRef) -> String {
+    element.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn first_text(root: ElementRef, css: &str) -> Option<String> {
+    root.select(&selector(css)).next().map(text).filter(|x| !x.is_empty())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
+
+// ==========================
+// Cache
+// ==========================
+
+/// Item details fetched before, so titles can be shown without fetching again
+#[derive(Default, Serialize, Deserialize)]
+pub struct Cache {
+    pub items: HashMap<ItemId, ItemDetails>
+}
+
+impl Cache {
+    pub fn load(path: &str) -> Self {
+        let text_data = match std::fs::read_to_string(path) {
+            Ok(text_data) => text_data,
+            Err(_) => return Cache::default()
+        };
+
+        match serde_json::from_str::<Cache>(&text_data) {
+            Ok(cache) => cache,
+            Err(_) => {
+                log(
+                    LogLevel::WARN,
+                    format!("Invalid json format for '{}', starting with an empty cache", path)
+                );
+                Cache::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        let str_data = serde_json::to_string(&self).unwrap();
+
+        if std::fs::write(path, str_data).is_err() {
+            log(
+                LogLevel::ERR,
+                format!("Couldn't write the cache to '{}'", path)
+            );
+        }
+    }
+
+    pub fn get(&self, id: ItemId) -> Option<&ItemDetails> {
+        self.items.get(&id)
+    }
+
+    pub fn insert(&mut self, details: ItemDetails) {
+        self.items.insert(details.id, details);
+    }
+
+    pub fn title(&self, id: ItemId) -> Option<String> {
+        self.items.get(&id).map(|x| x.title.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::{Config, ConfigProperties};
+
+    const ITEM_PAGE: &str = include_str!("../fixtures/item.html");
+    const COLLECTION_PAGE: &str = include_str!("../fixtures/collection.html");
+    const BROWSE_PAGE: &str = include_str!("../fixtures/browse.html");
+    const ERROR_PAGE: &str = include_str!("../fixtures/error.html");
+
+    #[test]
+    fn parse_ids() {
+        assert_eq!(parse_id("2009463077"), Some(ItemId(2009463077)));
+        assert_eq!(parse_id(" https://steamcommunity.com/sharedfiles/filedetails/?id=123&searchtext= "), Some(ItemId(123)));
+        assert_eq!(parse_id("https://steamcommunity.com/workshop/filedetails/?searchtext=x&id=456"), Some(ItemId(456)));
+        assert_eq!(parse_id("https://steamcommunity.com/app/294100/workshop/"), None);
+        assert_eq!(parse_id("rimworld"), None);
+    }
+
+    #[test]
+    fn item_page() {
+        let item = parse_item_page(ItemId(111), &Html::parse_document(ITEM_PAGE)).unwrap();
+
+        assert_eq!(item.id, ItemId(111));
+        assert_eq!(item.app_id.as_deref(), Some("294100"));
+        assert_eq!(item.title, "Hospitality");
+        assert_eq!(item.author.as_deref(), Some("Orion"));
+        assert_eq!(item.size.as_deref(), Some("12.345 MB"));
+        assert_eq!(item.posted.as_deref(), Some("2 Mar, 2017 @ 1:39pm"));
+        assert_eq!(item.updated.as_deref(), Some("15 Oct @ 9:02am"));
+        assert_eq!(item.tags, ["Mod", "1.4"]);
+        assert_eq!(item.rating.as_deref(), Some("4/5 (12,345 ratings)"));
+        assert_eq!(item.subscribers, Some(234567));
+        assert_eq!(item.description, "Guests visit your colony. They pay for beds and food. Lots of text here.");
+
+        let required: Vec<(ItemId, &str)> = item.required_items.iter().map(|x| (x.id, x.title.as_str())).collect();
+        assert_eq!(required, [(ItemId(2009463077), "Harmony"), (ItemId(818773962), "HugsLib")]);
+
+        assert_eq!(item.required_dlc.len(), 1);
+        assert_eq!(item.required_dlc[0].app_id, "1149640");
+        assert_eq!(item.required_dlc[0].name, "RimWorld - Royalty");
+    }
+
+    #[test]
+    fn collection_page() {
+        let collection = parse_collection_page(ItemId(5), &Html::parse_document(COLLECTION_PAGE)).unwrap();
+
+        assert_eq!(collection.id, ItemId(5));
+        assert_eq!(collection.app_id.as_deref(), Some("294100"));
+        assert_eq!(collection.title, "Our Modpack");
+        assert_eq!(collection.items, [(ItemId(111), String::from("Hospitality")), (ItemId(222), String::from("Harmony"))]);
+        assert_eq!(collection.collections, [(ItemId(900), String::from("QoL Pack"))]);
+    }
+
+    #[test]
+    fn browse_page() {
+        let page = parse_browse_page(&Html::parse_document(BROWSE_PAGE)).unwrap();
+
+        assert_eq!(page.title.as_deref(), Some("Steam Workshop :: TestGame"));
+        assert_eq!(page.items, [(ItemId(111), String::from("First mod")), (ItemId(222), String::from("Second mod"))]);
+
+        // an item without an id is left out instead of failing the whole page
+        let page = parse_browse_page(&Html::parse_document(&BROWSE_PAGE.replace(r#" data-publishedfileid="111""#, ""))).unwrap();
+        assert_eq!(page.items, [(ItemId(222), String::from("Second mod"))]);
+    }
+
+    #[test]
+    fn pages_of_the_wrong_kind() {
+        assert!(parse_item_page(ItemId(1), &Html::parse_document(ERROR_PAGE)).is_none());
+        assert!(parse_collection_page(ItemId(1), &Html::parse_document(ERROR_PAGE)).is_none());
+        assert!(parse_browse_page(&Html::parse_document(ERROR_PAGE)).is_none());
+        // an item isn't a collection
+        assert!(parse_collection_page(ItemId(111), &Html::parse_document(ITEM_PAGE)).is_none());
+    }
+
+    #[test]
+    fn community_url() {
+        assert_eq!(item_url("http://127.0.0.1:8080/steam/", ItemId(111)), "http://127.0.0.1:8080/steam/sharedfiles/filedetails/?id=111");
+
+        // the only test that touches the variable
+        std::env::remove_var(COMMUNITY_URL_ENV_VAR);
+        let mut config = Config::new();
+        assert_eq!(config.community_url(), DEFAULT_COMMUNITY_URL);
+
+        let mut props = ConfigProperties::new();
+        props.community_url = Some(String::from("http://mirror.local/steam/"));
+        config.properties = Some(props);
+        assert_eq!(config.community_url(), "http://mirror.local/steam");
+
+        std::env::set_var(COMMUNITY_URL_ENV_VAR, "http://127.0.0.1:8080");
+        assert_eq!(config.community_url(), "http://127.0.0.1:8080");
+        std::env::remove_var(COMMUNITY_URL_ENV_VAR);
+    }
+}